@@ -0,0 +1,343 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value as JSONValue;
+
+use crate::tokenizer::Tokenizer;
+
+/// How field values that are not strings become text.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ValueCoercion {
+    /// Each element of an array and each value of an object is tokenized on
+    /// its own, so tokens never span two elements. Booleans become
+    /// `true`/`false` and numbers are formatted by serde_json.
+    #[default]
+    Elements,
+    /// JavaScript's `String(value)`, like MiniSearch: arrays are joined with
+    /// commas, objects become `[object Object]` and numbers use the
+    /// javascript formatting.
+    Js,
+}
+
+pub fn get_document_tokens(
+    field_ids: &HashMap<String, usize>,
+    document: &HashMap<String, Vec<String>>,
+    document_id: usize,
+    tokenizer: &Tokenizer,
+    field_tokenizers: &HashMap<String, Tokenizer>,
+) -> Vec<(String, usize, usize)> {
+    let default = &vec![];
+    field_ids
+        .iter()
+        .flat_map(|(field_name, field_id)| {
+            let field_tokenizer = field_tokenizers.get(field_name).unwrap_or(tokenizer);
+            document
+                .get(field_name)
+                .unwrap_or(default)
+                .iter()
+                .flat_map(move |text| field_tokenizer.tokenize(text))
+                .map(|x| (x.to_owned(), *field_id, document_id.to_owned()))
+        })
+        .collect()
+}
+
+/* the texts of a value, where null has none */
+fn value_texts(value: &JSONValue, coercion: ValueCoercion, texts: &mut Vec<String>) {
+    match (value, coercion) {
+        (JSONValue::Null, _) => (),
+        (JSONValue::String(s), _) => texts.push(s.clone()),
+        (JSONValue::Bool(b), _) => texts.push(b.to_string()),
+        (JSONValue::Number(n), ValueCoercion::Elements) => texts.push(n.to_string()),
+        (JSONValue::Array(a), ValueCoercion::Elements) => {
+            for element in a.iter() {
+                value_texts(element, coercion, texts);
+            }
+        }
+        (JSONValue::Object(o), ValueCoercion::Elements) => {
+            for element in o.values() {
+                value_texts(element, coercion, texts);
+            }
+        }
+        (value, ValueCoercion::Js) => texts.push(js_string(value)),
+    }
+}
+
+/// JavaScript's `String(value)`.
+pub fn js_string(value: &JSONValue) -> String {
+    match value {
+        JSONValue::Null => "null".to_owned(),
+        JSONValue::Bool(b) => b.to_string(),
+        JSONValue::Number(n) => js_number_string(n.as_f64().unwrap_or(f64::NAN)),
+        JSONValue::String(s) => s.clone(),
+        /* `Array.prototype.join` turns null into an empty string */
+        JSONValue::Array(a) => a
+            .iter()
+            .map(|element| match element {
+                JSONValue::Null => "".to_owned(),
+                element => js_string(element),
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+        JSONValue::Object(_) => "[object Object]".to_owned(),
+    }
+}
+
+/* Number.prototype.toString(), from the shortest digits that round trip */
+fn js_number_string(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_owned();
+    }
+    if n == 0.0 {
+        return "0".to_owned();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_owned();
+    }
+    let sign = if n < 0.0 { "-" } else { "" };
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+    let number = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let exponent_sign = if n > 0 { "+" } else { "-" };
+        let mantissa = if k == 1 {
+            digits
+        } else {
+            format!("{}.{}", &digits[..1], &digits[1..])
+        };
+        format!("{}e{}{}", mantissa, exponent_sign, (n - 1).abs())
+    };
+    format!("{}{}", sign, number)
+}
+
+pub fn json_document_to_text_document(
+    json_document: &HashMap<String, JSONValue>,
+    fields: &HashSet<String>,
+    coercion: ValueCoercion,
+) -> HashMap<String, Vec<String>> {
+    fields
+        .iter()
+        .filter_map(|k| {
+            let v = extract_field(json_document, k)?;
+            let mut texts = vec![];
+            value_texts(&v, coercion, &mut texts);
+            Some((k.clone(), texts))
+        })
+        .collect()
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+/* the supported JSONPath subset: `$.a.b`, `$['a']`, `$.a[0]`, `$.a[*]`, `$.*` */
+fn parse_json_path(path: &str) -> Option<Vec<PathSegment>> {
+    let mut segments = vec![];
+    let mut rest = path.strip_prefix('$')?;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            segments.push(match &after[..end] {
+                "" => return None,
+                "*" => PathSegment::Wildcard,
+                key => PathSegment::Key(key.to_owned()),
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']')?;
+            let selector = after[..end].trim();
+            segments.push(if selector == "*" {
+                PathSegment::Wildcard
+            } else if let Ok(index) = selector.parse() {
+                PathSegment::Index(index)
+            } else {
+                let quoted = selector
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .or_else(|| selector.strip_prefix('"').and_then(|s| s.strip_suffix('"')))?;
+                PathSegment::Key(quoted.to_owned())
+            });
+            rest = &after[end + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
+
+fn children<'a>(value: &'a JSONValue, segment: &PathSegment) -> Vec<&'a JSONValue> {
+    match (value, segment) {
+        (JSONValue::Object(o), PathSegment::Key(key)) => o.get(key).into_iter().collect(),
+        /* like javascript, `a.0` indexes arrays too */
+        (JSONValue::Array(a), PathSegment::Key(key)) => key
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| a.get(i))
+            .into_iter()
+            .collect(),
+        (JSONValue::Array(a), PathSegment::Index(i)) => a.get(*i).into_iter().collect(),
+        (JSONValue::Object(o), PathSegment::Wildcard) => o.values().collect(),
+        (JSONValue::Array(a), PathSegment::Wildcard) => a.iter().collect(),
+        _ => vec![],
+    }
+}
+
+/// Gets a field of a document like MiniSearch's default `extractField`. The
+/// field is a top level key, a dotted path like `album.artist.name` or a
+/// JSONPath expression like `$.artists[*].name`. Expressions with wildcards
+/// extract the array of the values they match.
+pub fn extract_field<'a>(
+    document: &'a HashMap<String, JSONValue>,
+    field: &str,
+) -> Option<Cow<'a, JSONValue>> {
+    if let Some(value) = document.get(field) {
+        return Some(Cow::Borrowed(value));
+    }
+    let segments = if field.starts_with('$') {
+        match parse_json_path(field) {
+            Some(segments) => segments,
+            None => {
+                warn!("invalid JSONPath {}", field);
+                return None;
+            }
+        }
+    } else if field.contains('.') {
+        field
+            .split('.')
+            .map(|key| PathSegment::Key(key.to_owned()))
+            .collect()
+    } else {
+        return None;
+    };
+
+    let mut segments_iter = segments.iter();
+    let mut values: Vec<&JSONValue> = match segments_iter.next()? {
+        PathSegment::Key(key) => document.get(key).into_iter().collect(),
+        PathSegment::Wildcard => document.values().collect(),
+        PathSegment::Index(_) => vec![],
+    };
+    for segment in segments_iter {
+        values = values
+            .into_iter()
+            .flat_map(|value| children(value, segment))
+            .collect();
+    }
+    if segments
+        .iter()
+        .any(|segment| matches!(segment, PathSegment::Wildcard))
+    {
+        if values.is_empty() {
+            return None;
+        }
+        Some(Cow::Owned(JSONValue::Array(
+            values.into_iter().cloned().collect(),
+        )))
+    } else {
+        values.pop().map(Cow::Borrowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document() -> HashMap<String, JSONValue> {
+        serde_json::from_value(json!({
+            "id": 1,
+            "title": "Abbey Road",
+            "a.b": "dotted key",
+            "album": {
+                "artist": {"name": "The Beatles"},
+                "tracks": [{"title": "Come Together"}, {"title": "Something"}],
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extract_field() {
+        let document = document();
+        let extract = |field| extract_field(&document, field).map(|v| v.into_owned());
+        assert_eq!(extract("title"), Some(json!("Abbey Road")));
+        assert_eq!(extract("a.b"), Some(json!("dotted key")));
+        assert_eq!(extract("album.artist.name"), Some(json!("The Beatles")));
+        assert_eq!(extract("album.tracks.1.title"), Some(json!("Something")));
+        assert_eq!(extract("album.label.name"), None);
+        assert_eq!(extract("$.album.artist.name"), Some(json!("The Beatles")));
+        assert_eq!(
+            extract("$['album'].tracks[0].title"),
+            Some(json!("Come Together"))
+        );
+        assert_eq!(
+            extract("$.album.tracks[*].title"),
+            Some(json!(["Come Together", "Something"]))
+        );
+        assert_eq!(extract("$.album.label[*]"), None);
+        assert_eq!(extract("$.album.[0"), None);
+    }
+
+    #[test]
+    fn test_json_document_to_text_document() {
+        let fields = HashSet::from(["album.artist.name".to_owned(), "year".to_owned()]);
+        assert_eq!(
+            json_document_to_text_document(&document(), &fields, ValueCoercion::Elements),
+            HashMap::from([(
+                "album.artist.name".to_owned(),
+                vec!["The Beatles".to_owned()]
+            )])
+        );
+    }
+
+    #[test]
+    fn test_value_coercion() {
+        let document = serde_json::from_value(json!({
+            "tags": ["rock", ["pop", null], 1.5, true, {"mood": "happy"}],
+            "empty": null,
+        }))
+        .unwrap();
+        let fields = HashSet::from(["tags".to_owned(), "empty".to_owned()]);
+        let texts = json_document_to_text_document(&document, &fields, ValueCoercion::Elements);
+        assert_eq!(texts["tags"], vec!["rock", "pop", "1.5", "true", "happy"]);
+        assert_eq!(texts["empty"], Vec::<String>::new());
+        let texts = json_document_to_text_document(&document, &fields, ValueCoercion::Js);
+        assert_eq!(texts["tags"], vec!["rock,pop,,1.5,true,[object Object]"]);
+        assert_eq!(texts["empty"], Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_js_string() {
+        for (value, expected) in [
+            (json!(1), "1"),
+            (json!(-42), "-42"),
+            (json!(1.5), "1.5"),
+            (json!(0.1), "0.1"),
+            (json!(-0.0), "0"),
+            (json!(100.0), "100"),
+            (json!(123456789012345680000.0), "123456789012345680000"),
+            (json!(1e21), "1e+21"),
+            (json!(1.25e22), "1.25e+22"),
+            (json!(0.000001), "0.000001"),
+            (json!(1.5e-7), "1.5e-7"),
+            (json!(12345678901234567890u64), "12345678901234567000"),
+            (json!(false), "false"),
+            (json!(null), "null"),
+            (json!([1, [2, 3]]), "1,2,3"),
+            (json!({"a": 1}), "[object Object]"),
+        ] {
+            assert_eq!(js_string(&value), expected);
+        }
+    }
+}
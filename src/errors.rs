@@ -1,7 +1,40 @@
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 
 #[derive(Debug, Fail)]
 pub enum MinisearchIndexrsError {
-    #[fail(display = "item to index does not have an id field")]
-    MissingId,
+    #[fail(display = "item to index does not have an id field ({})", _0)]
+    MissingId(String),
+    #[fail(display = "invalid id {}: ids cannot be null, arrays or objects", _0)]
+    InvalidId(String),
+    #[fail(display = "document at index {}: {}", _0, _1)]
+    InvalidDocument(usize, String),
+    #[fail(display = "invalid configuration: {}", _0)]
+    InvalidConfig(String),
+    #[fail(display = "{}: {}", _0, _1)]
+    InvalidInput(String, String),
+    #[fail(display = "line {}: {}", _0, _1)]
+    InvalidLine(usize, String),
+    #[fail(display = "invalid page: {}", _0)]
+    InvalidPage(String),
+    #[fail(display = "duplicate id {}, already in the index", _0)]
+    DuplicateId(String, usize),
+    #[fail(
+        display = "document at index {}: duplicate id {}, first seen at index {} of {}",
+        _0, _1, _2, _3
+    )]
+    DuplicateDocument(usize, String, usize, String),
+    #[fail(display = "unknown serialization format: {}", _0)]
+    UnknownFormat(String),
+    #[fail(display = "missing argument: {}", _0)]
+    MissingArgument(&'static str),
+    #[fail(display = "document {} is not in the index", _0)]
+    UnknownDocument(String),
+    #[fail(display = "invalid index: {}", _0)]
+    InvalidIndex(String),
+    #[fail(display = "unknown stop word list: {}", _0)]
+    UnknownStopwords(String),
+    #[fail(display = "unknown stemmer language: {}", _0)]
+    UnknownLanguage(String),
 }
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
@@ -9,21 +10,43 @@ use patricia_tree::PatriciaMap;
 use serde::Deserialize;
 use serde_json::{Map as JSONMap, Value as JSONValue};
 
-use crate::serializer;
+use crate::document::{self, ValueCoercion};
+use crate::errors::MinisearchIndexrsError;
+use crate::search::{deserialize_auto_suggest_options, SearchOptions};
+use crate::serializer::{self, invalid_index};
+use crate::term_processor::{TermContext, TermProcessor};
+use crate::tokenizer::Tokenizer;
 
 pub struct Index {
-    field_ids: HashMap<String, usize>,
-    document_ids: JSONMap<String, JSONValue>,
+    pub(crate) field_ids: HashMap<String, usize>,
+    /* the names in `field_ids`, to extract them from each document */
+    fields: HashSet<String>,
+    pub(crate) document_ids: JSONMap<String, JSONValue>,
+    /* {serialized id: documentId}, of the first document with the id */
+    small_ids: HashMap<String, usize>,
+    /* {serialized id: [documentId]} of the later ones, only if duplicates
+     * are allowed */
+    duplicate_ids: HashMap<String, Vec<usize>>,
+    pub(crate) document_count: usize,
     next_id: usize,
     /* {fieldId: count} */
-    field_num_tokens: HashMap<usize, usize>,
+    pub(crate) field_num_tokens: HashMap<usize, usize>,
     /* {documentId: {fieldId: count} } */
-    field_length: HashMap<usize, HashMap<usize, usize>>,
-    map: PatriciaMap<Vec<(usize, usize)>>,
+    pub(crate) field_length: HashMap<usize, HashMap<usize, usize>>,
+    pub(crate) map: PatriciaMap<Vec<(usize, usize)>>,
+    /* documentIds removed while their postings are still in `map`, which
+     * `purge_removed` drops in a single walk of the terms */
+    pub(crate) removed: HashSet<usize>,
     store_fields: Vec<String>,
-    stored_fields: JSONMap<String, JSONValue>,
-    // TODO: custom tokenizer
-    // TODO: custom term processing
+    pub(crate) stored_fields: JSONMap<String, JSONValue>,
+    search_options: SearchOptions,
+    auto_suggest_options: SearchOptions,
+    pub(crate) tokenizer: Tokenizer,
+    field_tokenizers: HashMap<String, Tokenizer>,
+    pub(crate) term_processor: TermProcessor,
+    value_coercion: ValueCoercion,
+    id_field: String,
+    on_duplicate: OnDuplicate,
 }
 
 impl Index {
@@ -35,29 +58,279 @@ impl Index {
             .map(|(i, v)| (v, i))
             .collect::<HashMap<String, usize>>();
         Index {
+            fields: field_ids.keys().cloned().collect(),
             field_ids,
             store_fields: config.store_fields,
             stored_fields: JSONMap::new(),
             document_ids: JSONMap::new(),
+            small_ids: HashMap::new(),
+            duplicate_ids: HashMap::new(),
             field_num_tokens: HashMap::new(),
             field_length: HashMap::new(),
+            document_count: 0,
             next_id: 0,
             map: PatriciaMap::new(),
+            removed: HashSet::new(),
+            search_options: config.search_options,
+            auto_suggest_options: config.auto_suggest_options,
+            tokenizer: config.tokenizer,
+            field_tokenizers: config.field_tokenizers,
+            term_processor: config.process_term,
+            value_coercion: config.value_coercion,
+            id_field: config.id_field,
+            on_duplicate: config.on_duplicate,
         }
     }
 
+    /// Rebuilds an index serialized by MiniSearch or by `into_minisearch_json`,
+    /// in either the legacy or the array-based format. The fields in `config`
+    /// must match the ones the index was built with.
+    pub fn from_minisearch_json(json: &str, config: IndexConfig) -> Result<Self, failure::Error> {
+        let mut h: JSONMap<String, JSONValue> = serde_json::from_str(json)?;
+        let mut index = Index::new(config);
+
+        let field_ids = match h.get("fieldIds") {
+            Some(JSONValue::Object(field_ids)) => serializer::parse_field_ids_json(field_ids)?,
+            _ => return Err(invalid_index("missing fieldIds")),
+        };
+        if field_ids != index.field_ids {
+            return Err(invalid_index("fields do not match the configuration"));
+        }
+        index.document_count =
+            h.get("documentCount")
+                .and_then(|c| c.as_u64())
+                .ok_or_else(|| invalid_index("missing documentCount"))? as usize;
+        index.next_id = h
+            .get("nextId")
+            .and_then(|c| c.as_u64())
+            .ok_or_else(|| invalid_index("missing nextId"))? as usize;
+        index.document_ids = match h.remove("documentIds") {
+            Some(JSONValue::Object(document_ids)) => document_ids,
+            _ => return Err(invalid_index("missing documentIds")),
+        };
+        for (small_id, id) in index.document_ids.iter() {
+            let small_id = small_id
+                .parse()
+                .map_err(|_| invalid_index("document ids must be numeric"))?;
+            insert_small_id(
+                &mut index.small_ids,
+                &mut index.duplicate_ids,
+                id_key(id),
+                small_id,
+            );
+        }
+        index.stored_fields = match h.remove("storedFields") {
+            Some(JSONValue::Object(stored_fields)) => stored_fields,
+            None => JSONMap::new(),
+            _ => return Err(invalid_index("storedFields must be an object")),
+        };
+        index.field_length = match h.get("fieldLength") {
+            Some(JSONValue::Object(field_length)) => {
+                serializer::parse_field_length_json(field_length)?
+            }
+            _ => return Err(invalid_index("missing fieldLength")),
+        };
+        for field_lengths in index.field_length.values() {
+            for (field_id, length) in field_lengths.iter() {
+                *index.field_num_tokens.entry(*field_id).or_insert(0) += length;
+            }
+        }
+        index.map = match h.get("index") {
+            Some(JSONValue::Array(entries)) => serializer::parse_index_json(entries)?,
+            Some(JSONValue::Object(tree)) => serializer::parse_map_json(tree)?,
+            _ => return Err(invalid_index("missing index")),
+        };
+        Ok(index)
+    }
+
     pub fn insert_document(&mut self, id: JSONValue) -> usize {
         let small_id = self.next_id;
+        insert_small_id(
+            &mut self.small_ids,
+            &mut self.duplicate_ids,
+            id_key(&id),
+            small_id,
+        );
         self.document_ids.insert(small_id.to_string(), id);
+        self.document_count += 1;
         self.next_id += 1;
         small_id
     }
 
+    /// Indexes a document, taking its id from the `idField` of the
+    /// configuration. Ids must be strings, numbers or booleans. Documents
+    /// with an id already in the index are handled as `onDuplicate` says.
+    pub fn add_document(
+        &mut self,
+        doc: HashMap<String, JSONValue>,
+    ) -> Result<usize, failure::Error> {
+        let id = document::extract_field(&doc, &self.id_field)
+            .ok_or_else(|| MinisearchIndexrsError::MissingId(self.id_field.clone()))?
+            .into_owned();
+        if matches!(
+            id,
+            JSONValue::Null | JSONValue::Array(_) | JSONValue::Object(_)
+        ) {
+            return Err(MinisearchIndexrsError::InvalidId(id.to_string()).into());
+        }
+        let key = id_key(&id);
+        if let Some(&small_id) = self.small_ids.get(&key) {
+            match self.on_duplicate {
+                OnDuplicate::Error => {
+                    return Err(
+                        MinisearchIndexrsError::DuplicateId(id.to_string(), small_id).into(),
+                    )
+                }
+                OnDuplicate::KeepFirst => {
+                    debug!("skipping document with duplicate id {}", id);
+                    return Ok(small_id);
+                }
+                /* replacing many documents walks the terms once, when purging */
+                OnDuplicate::KeepLast => {
+                    let small_ids = self.small_ids_of(&key).collect();
+                    self.forget_documents(&small_ids);
+                    self.removed.extend(small_ids);
+                }
+                OnDuplicate::Allow => (),
+            }
+        }
+        self.add_document_with_id(id, doc)
+    }
+
+    fn add_document_with_id(
+        &mut self,
+        id: JSONValue,
+        doc: HashMap<String, JSONValue>,
+    ) -> Result<usize, failure::Error> {
+        let small_id = self.insert_document(id);
+        let text_doc =
+            document::json_document_to_text_document(&doc, &self.fields, self.value_coercion);
+        let tokens = document::get_document_tokens(
+            &self.field_ids,
+            &text_doc,
+            small_id,
+            &self.tokenizer,
+            &self.field_tokenizers,
+        );
+        self.index_tokens(tokens.into_iter(), Some(&doc))?;
+        self.add_document_fields(std::iter::once((small_id, doc)));
+        Ok(small_id)
+    }
+
+    pub fn remove_document(&mut self, id: &JSONValue) -> Result<(), failure::Error> {
+        self.remove_documents(std::iter::once(id))
+    }
+
+    /// Removes several documents walking the terms only once. Fails without
+    /// removing anything if one of the ids is not in the index.
+    pub fn remove_documents<'a, I>(&mut self, ids: I) -> Result<(), failure::Error>
+    where
+        I: Iterator<Item = &'a JSONValue>,
+    {
+        let mut small_ids = HashSet::new();
+        for id in ids {
+            let key = id_key(id);
+            if !self.small_ids.contains_key(&key) {
+                return Err(MinisearchIndexrsError::UnknownDocument(id.to_string()).into());
+            }
+            small_ids.extend(self.small_ids_of(&key));
+        }
+        self.forget_documents(&small_ids);
+        self.removed.extend(small_ids);
+        self.purge_removed();
+        Ok(())
+    }
+
+    /* the documentIds of an id, several only if duplicates are allowed */
+    fn small_ids_of<'a>(&'a self, key: &str) -> impl Iterator<Item = usize> + 'a {
+        let duplicates = self.duplicate_ids.get(key).into_iter().flatten();
+        self.small_ids
+            .get(key)
+            .into_iter()
+            .chain(duplicates)
+            .copied()
+    }
+
+    /* removes everything about the documents but their postings */
+    fn forget_documents(&mut self, small_ids: &HashSet<usize>) {
+        for small_id in small_ids.iter() {
+            if let Some(id) = self.document_ids.remove(&small_id.to_string()) {
+                let key = id_key(&id);
+                self.small_ids.remove(&key);
+                self.duplicate_ids.remove(&key);
+            }
+            self.stored_fields.remove(&small_id.to_string());
+            for (field_id, length) in self.field_length.remove(small_id).unwrap_or_default() {
+                if let Some(num_tokens) = self.field_num_tokens.get_mut(&field_id) {
+                    *num_tokens -= length;
+                }
+            }
+            self.document_count -= 1;
+        }
+    }
+
+    /// Drops the postings of the documents replaced by `onDuplicate:
+    /// keepLast`. Searches skip them, and serializing purges them first.
+    pub fn purge_removed(&mut self) {
+        if self.removed.is_empty() {
+            return;
+        }
+        let mut empty_terms = vec![];
+        for (term, postings) in self.map.iter_mut() {
+            postings.retain(|(small_id, _)| !self.removed.contains(small_id));
+            if postings.is_empty() {
+                empty_terms.push(term);
+            }
+        }
+        for term in empty_terms {
+            self.map.remove(term);
+        }
+        self.removed.clear();
+    }
+
+    /// Replaces the document with the given id, which the new document keeps.
+    pub fn replace_document(
+        &mut self,
+        id: &JSONValue,
+        doc: HashMap<String, JSONValue>,
+    ) -> Result<usize, failure::Error> {
+        self.remove_document(id)?;
+        self.add_document_with_id(id.clone(), doc)
+    }
+
     pub fn add_document_tokens<I>(&mut self, document_tokens: I) -> Result<(), failure::Error>
     where
         I: Iterator<Item = (String, usize, usize)>,
     {
+        self.index_tokens(document_tokens, None)
+    }
+
+    /* `document` is the source of the tokens, used by the term processor */
+    fn index_tokens<I>(
+        &mut self,
+        document_tokens: I,
+        document: Option<&HashMap<String, JSONValue>>,
+    ) -> Result<(), failure::Error>
+    where
+        I: Iterator<Item = (String, usize, usize)>,
+    {
+        let field_names = self
+            .field_ids
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect::<HashMap<usize, String>>();
         for (token, field_id, small_id) in document_tokens {
+            let context = TermContext {
+                field: field_names.get(&field_id).map(|name| name.as_str()),
+                document,
+                indexing: true,
+            };
+            /* a token counts once towards the field length, however many
+             * terms it yields, and not at all when it is dropped */
+            let terms = self.term_processor.process(&token, context);
+            if terms.is_empty() {
+                continue;
+            }
             let num_tokens = self.field_num_tokens.get(&field_id).unwrap_or(&0) + 1;
             self.field_num_tokens.insert(field_id, num_tokens);
 
@@ -71,7 +344,9 @@ impl Index {
             self.field_length.insert(small_id, document_fields_length);
 
             self.field_num_tokens.insert(field_id, num_tokens);
-            self.add_token(small_id, &process_term(&token), field_id);
+            for term in terms.iter() {
+                self.add_token(small_id, term, field_id);
+            }
         }
         Ok(())
     }
@@ -91,15 +366,30 @@ impl Index {
         self.field_ids.clone()
     }
 
+    /// The `onDuplicate` policy from the configuration.
+    pub fn on_duplicate(&self) -> OnDuplicate {
+        self.on_duplicate
+    }
+
+    /// The `searchOptions` from the configuration.
+    pub fn search_options(&self) -> &SearchOptions {
+        &self.search_options
+    }
+
+    /// The `autoSuggestOptions` from the configuration.
+    pub fn auto_suggest_options(&self) -> &SearchOptions {
+        &self.auto_suggest_options
+    }
+
     pub fn add_document_fields<I>(&mut self, docs: I)
     where
         I: Iterator<Item = (usize, HashMap<String, JSONValue>)>,
     {
-        for (small_id, mut doc) in docs.into_iter() {
+        for (small_id, doc) in docs.into_iter() {
             let mut json_dic = JSONMap::new();
             for f in self.store_fields.iter() {
-                if let Some(val) = doc.remove(f) {
-                    json_dic.insert(f.clone(), val);
+                if let Some(val) = document::extract_field(&doc, f) {
+                    json_dic.insert(f.clone(), val.into_owned());
                 }
             }
             self.stored_fields
@@ -107,26 +397,59 @@ impl Index {
         }
     }
 
-    pub fn into_minisearch_json(self) -> Result<String, failure::Error> {
+    pub fn into_minisearch_json(
+        mut self,
+        format: serializer::Format,
+    ) -> Result<String, failure::Error> {
+        self.purge_removed();
+        let num_fields = self.field_ids.len();
         let mut h = JSONMap::new();
-        h.insert("documentCount".to_string(), self.next_id.into());
+        h.insert("documentCount".to_string(), self.document_count.into());
         h.insert("nextId".to_string(), self.next_id.into());
         h.insert("documentIds".to_string(), self.document_ids.into());
         h.insert(
             "fieldIds".to_string(),
             serializer::field_ids_json(self.field_ids).into(),
         );
-        h.insert(
-            "averageFieldLength".to_string(),
-            serializer::average_field_length_json(self.field_num_tokens, self.next_id as f64)
-                .into(),
-        );
-        h.insert(
-            "fieldLength".to_string(),
-            serializer::field_length_json(self.field_length).into(),
-        );
-        h.insert("index".to_string(), serializer::map_json(self.map)?.into());
         h.insert("storedFields".to_string(), self.stored_fields.into());
+        match format {
+            serializer::Format::Legacy => {
+                h.insert(
+                    "averageFieldLength".to_string(),
+                    serializer::average_field_length_json(
+                        self.field_num_tokens,
+                        self.document_count as f64,
+                    )
+                    .into(),
+                );
+                h.insert(
+                    "fieldLength".to_string(),
+                    serializer::field_length_json(self.field_length).into(),
+                );
+                h.insert("index".to_string(), serializer::map_json(self.map)?.into());
+            }
+            serializer::Format::V2 => {
+                h.insert(
+                    "averageFieldLength".to_string(),
+                    serializer::average_field_length_array_json(
+                        self.field_num_tokens,
+                        num_fields,
+                        self.document_count as f64,
+                    )
+                    .into(),
+                );
+                h.insert(
+                    "fieldLength".to_string(),
+                    serializer::field_length_array_json(self.field_length, num_fields).into(),
+                );
+                h.insert("dirtCount".to_string(), 0.into());
+                h.insert(
+                    "index".to_string(),
+                    serializer::index_json(self.map)?.into(),
+                );
+                h.insert("serializationVersion".to_string(), 2.into());
+            }
+        }
 
         Ok(serde_json::to_string(&JSONValue::Object(h)).unwrap())
     }
@@ -137,6 +460,62 @@ pub struct IndexConfig {
     fields: Vec<String>,
     #[serde(alias = "storeFields")]
     store_fields: Vec<String>,
+    #[serde(alias = "searchOptions", default)]
+    search_options: SearchOptions,
+    #[serde(
+        alias = "autoSuggestOptions",
+        default = "SearchOptions::auto_suggest",
+        deserialize_with = "deserialize_auto_suggest_options"
+    )]
+    auto_suggest_options: SearchOptions,
+    #[serde(default)]
+    tokenizer: Tokenizer,
+    #[serde(alias = "fieldTokenizers", default)]
+    field_tokenizers: HashMap<String, Tokenizer>,
+    #[serde(alias = "processTerm", default)]
+    process_term: TermProcessor,
+    #[serde(alias = "valueCoercion", default)]
+    value_coercion: ValueCoercion,
+    #[serde(alias = "idField", default = "default_id_field")]
+    id_field: String,
+    #[serde(alias = "onDuplicate", default)]
+    on_duplicate: OnDuplicate,
+}
+
+/// What to do with a document whose id is already in the index.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OnDuplicate {
+    /// Fails, like MiniSearch.
+    #[default]
+    Error,
+    /// Ignores the new document.
+    KeepFirst,
+    /// Replaces the earlier document.
+    KeepLast,
+    /// Indexes both. Removing the id removes all of them.
+    Allow,
+}
+
+pub(crate) fn default_id_field() -> String {
+    "id".to_owned()
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            fields: vec![],
+            store_fields: vec![],
+            search_options: SearchOptions::default(),
+            auto_suggest_options: SearchOptions::auto_suggest(),
+            tokenizer: Tokenizer::default(),
+            field_tokenizers: HashMap::new(),
+            process_term: TermProcessor::default(),
+            value_coercion: ValueCoercion::default(),
+            id_field: default_id_field(),
+            on_duplicate: OnDuplicate::default(),
+        }
+    }
 }
 
 pub fn read_config_from_file<P: AsRef<Path>>(path: P) -> Result<IndexConfig, Error> {
@@ -146,21 +525,45 @@ pub fn read_config_from_file<P: AsRef<Path>>(path: P) -> Result<IndexConfig, Err
     Ok(serde_json::from_reader(reader)?)
 }
 
-fn process_term(term: &str) -> String {
-    term.to_lowercase()
+/* ids are compared by their JSON serialization, so 1 and "1" differ */
+fn id_key(id: &JSONValue) -> String {
+    id.to_string()
+}
+
+fn insert_small_id(
+    small_ids: &mut HashMap<String, usize>,
+    duplicate_ids: &mut HashMap<String, Vec<usize>>,
+    key: String,
+    small_id: usize,
+) {
+    match small_ids.entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(small_id);
+        }
+        Entry::Occupied(entry) => duplicate_ids
+            .entry(entry.key().clone())
+            .or_default()
+            .push(small_id),
+    }
+}
+
+pub fn read_index_from_file<P: AsRef<Path>>(path: P, config: IndexConfig) -> Result<Index, Error> {
+    debug!("reading index from {}", path.as_ref().to_string_lossy());
+    let json = std::fs::read_to_string(path)?;
+    Index::from_minisearch_json(&json, config)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
-    use std::array::IntoIter;
 
     #[test]
     fn test_insert_document() {
         let mut index = Index::new(IndexConfig {
             fields: vec!["author".to_string(), "title".to_string()],
             store_fields: vec!["author".to_string(), "title".to_string()],
+            ..Default::default()
         });
 
         index.insert_document("id1".into());
@@ -187,6 +590,7 @@ mod tests {
         let mut index = Index::new(IndexConfig {
             fields: vec!["author".to_string(), "title".to_string()],
             store_fields: vec!["author".to_string(), "title".to_string()],
+            ..Default::default()
         });
         index
             .add_document_tokens(
@@ -209,19 +613,20 @@ mod tests {
         let mut index = Index::new(IndexConfig {
             fields: vec!["author".to_string(), "title".to_string()],
             store_fields: vec!["author".to_string(), "title".to_string()],
+            ..Default::default()
         });
         index.add_document_fields(
             vec![
                 (
                     1,
-                    HashMap::<_, _>::from_iter(IntoIter::new([(
+                    HashMap::<_, _>::from_iter(IntoIterator::into_iter([(
                         "author".to_owned(),
                         JSONValue::String("J. K. Rowling".to_owned()),
                     )])),
                 ),
                 (
                     3,
-                    HashMap::<_, _>::from_iter(IntoIter::new([(
+                    HashMap::<_, _>::from_iter(IntoIterator::into_iter([(
                         "author".to_owned(),
                         JSONValue::String("Stephen King".to_owned()),
                     )])),
@@ -239,4 +644,372 @@ mod tests {
             .unwrap()
         );
     }
+
+    #[test]
+    fn test_from_minisearch_json() {
+        let config = IndexConfig {
+            fields: vec!["author".to_string(), "title".to_string()],
+            store_fields: vec!["author".to_string()],
+            ..Default::default()
+        };
+        for format in [serializer::Format::Legacy, serializer::Format::V2] {
+            let mut index = Index::new(config.clone());
+            let first = index.insert_document("id1".into());
+            let second = index.insert_document("id2".into());
+            index
+                .add_document_tokens(
+                    vec![
+                        ("foo".to_owned(), 0, first),
+                        ("bar".to_owned(), 1, first),
+                        ("foo".to_owned(), 1, first),
+                        ("foo".to_owned(), 0, second),
+                    ]
+                    .into_iter(),
+                )
+                .unwrap();
+            index.add_document_fields(
+                vec![(
+                    first,
+                    HashMap::from([("author".to_owned(), JSONValue::from("Stephen King"))]),
+                )]
+                .into_iter(),
+            );
+            let json = index.into_minisearch_json(format).unwrap();
+
+            let mut index = Index::from_minisearch_json(&json, config.clone()).unwrap();
+            assert_eq!(index.document_count, 2);
+            assert_eq!(index.next_id, 2);
+            assert_eq!(
+                &index.document_ids,
+                json!({"0": "id1", "1": "id2"}).as_object().unwrap()
+            );
+            assert_eq!(
+                &index.stored_fields,
+                json!({"0": {"author": "Stephen King"}})
+                    .as_object()
+                    .unwrap()
+            );
+            // v2 stores a length for every field, even when it is zero
+            let second_lengths = match format {
+                serializer::Format::Legacy => HashMap::from([(0, 1)]),
+                serializer::Format::V2 => HashMap::from([(0, 1), (1, 0)]),
+            };
+            assert_eq!(
+                index.field_length,
+                HashMap::from([(0, HashMap::from([(0, 1), (1, 2)])), (1, second_lengths)])
+            );
+            assert_eq!(index.field_num_tokens, HashMap::from([(0, 2), (1, 2)]));
+            index.map.get_mut("foo").unwrap().sort_unstable();
+            assert_eq!(index.map.get("foo"), Some(&vec![(0, 0), (0, 1), (1, 0)]));
+            assert_eq!(index.map.get("bar"), Some(&vec![(0, 1)]));
+        }
+    }
+
+    #[test]
+    fn test_from_minisearch_json_field_mismatch() {
+        let index = Index::new(IndexConfig {
+            fields: vec!["author".to_string(), "title".to_string()],
+            store_fields: vec![],
+            ..Default::default()
+        });
+        let json = index.into_minisearch_json(serializer::Format::V2).unwrap();
+        assert!(Index::from_minisearch_json(
+            &json,
+            IndexConfig {
+                fields: vec!["title".to_string(), "author".to_string()],
+                store_fields: vec![],
+                ..Default::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_remove_document() {
+        let mut index = Index::new(IndexConfig {
+            fields: vec!["title".to_string()],
+            store_fields: vec!["title".to_string()],
+            ..Default::default()
+        });
+        for doc in [
+            json!({"id": 1, "title": "harry potter"}),
+            json!({"id": "1", "title": "the hobbit"}),
+            json!({"id": 3, "title": "harry and the hobbit"}),
+        ] {
+            index
+                .add_document(serde_json::from_value(doc).unwrap())
+                .unwrap();
+        }
+
+        index.remove_document(&json!(1)).unwrap();
+        assert_eq!(index.document_count, 2);
+        assert_eq!(index.next_id, 3);
+        assert_eq!(
+            &index.document_ids,
+            json!({"1": "1", "2": 3}).as_object().unwrap()
+        );
+        assert_eq!(
+            &index.stored_fields,
+            json!({"1": {"title": "the hobbit"}, "2": {"title": "harry and the hobbit"}})
+                .as_object()
+                .unwrap()
+        );
+        assert_eq!(index.field_num_tokens, HashMap::from([(0, 6)]));
+        assert!(!index.field_length.contains_key(&0));
+        assert_eq!(index.map.get("harry"), Some(&vec![(2, 0)]));
+        assert_eq!(index.map.get("potter"), None);
+
+        assert!(index.remove_document(&json!(1)).is_err());
+    }
+
+    #[test]
+    fn test_replace_document() {
+        let mut index = Index::new(IndexConfig {
+            fields: vec!["title".to_string()],
+            store_fields: vec!["title".to_string()],
+            ..Default::default()
+        });
+        index
+            .add_document(
+                serde_json::from_value(json!({"id": "a", "title": "harry potter"})).unwrap(),
+            )
+            .unwrap();
+        let small_id = index
+            .replace_document(
+                &json!("a"),
+                serde_json::from_value(json!({"title": "the hobbit"})).unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(small_id, 1);
+        assert_eq!(index.document_count, 1);
+        assert_eq!(&index.document_ids, json!({"1": "a"}).as_object().unwrap());
+        assert_eq!(index.map.get("harry"), None);
+        assert_eq!(index.map.get("hobbit"), Some(&vec![(1, 0)]));
+        assert_eq!(index.field_num_tokens, HashMap::from([(0, 2)]));
+    }
+
+    #[test]
+    fn test_field_tokenizers() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title", "tags"],
+            "storeFields": [],
+            "tokenizer": {"type": "whitespace"},
+            "fieldTokenizers": {"tags": {"type": "separator", "pattern": ","}},
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        index
+            .add_document(
+                serde_json::from_value(json!({
+                    "id": 1,
+                    "title": "Lonely-Hearts Club",
+                    "tags": "rock,psychedelic rock",
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(index.map.get("lonely-hearts"), Some(&vec![(0, 0)]));
+        assert_eq!(index.map.get("psychedelic rock"), Some(&vec![(0, 1)]));
+        assert_eq!(index.field_length[&0], HashMap::from([(0, 2), (1, 2)]));
+    }
+
+    #[test]
+    fn test_process_term() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title"],
+            "storeFields": [],
+            "processTerm": [
+                {"type": "lowercase"},
+                {"type": "foldDiacritics"},
+                {"type": "stopwords", "list": "english"},
+            ],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        index
+            .add_document(
+                serde_json::from_value(json!({"id": 1, "title": "The Éxodo of the Tribe"}))
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(index.map.get("exodo"), Some(&vec![(0, 0)]));
+        assert_eq!(index.map.get("the"), None);
+        assert_eq!(index.field_length[&0], HashMap::from([(0, 2)]));
+        assert_eq!(index.field_num_tokens, HashMap::from([(0, 2)]));
+        assert_eq!(index.search("EXODO", index.search_options()).len(), 1);
+    }
+
+    #[test]
+    fn test_stemming() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title"],
+            "storeFields": [],
+            "processTerm": [
+                {"type": "lowercase"},
+                {"type": "stem", "language": "english", "languageField": "lang"},
+            ],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        for doc in [
+            json!({"id": 1, "title": "Run to the Hills"}),
+            json!({"id": 2, "title": "Corriendo", "lang": "es"}),
+        ] {
+            index
+                .add_document(serde_json::from_value(doc).unwrap())
+                .unwrap();
+        }
+        assert_eq!(index.map.get("corr"), Some(&vec![(1, 0)]));
+        let results = index.search("running", index.search_options());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, json!(1));
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title"],
+            "storeFields": [],
+            "processTerm": [
+                {"type": "lowercase"},
+                {"type": "synonyms", "synonyms": {"nyc": "new york city"}},
+            ],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        index
+            .add_document(serde_json::from_value(json!({"id": 1, "title": "NYC Blues"})).unwrap())
+            .unwrap();
+        assert_eq!(index.map.get("york"), Some(&vec![(0, 0)]));
+        assert_eq!(index.field_length[&0], HashMap::from([(0, 2)]));
+        assert_eq!(index.search("new york", index.search_options()).len(), 1);
+        assert_eq!(index.search("nyc", index.search_options()).len(), 1);
+    }
+
+    #[test]
+    fn test_nested_fields() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title", "album.artist.name"],
+            "storeFields": ["album.artist.name"],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        index
+            .add_document(
+                serde_json::from_value(json!({
+                    "id": 1,
+                    "title": "Abbey Road",
+                    "album": {
+                        "artist": {"name": "The Beatles"},
+                        "tracks": [{"title": "Something"}],
+                    },
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let artist_field = index.field_ids["album.artist.name"];
+        assert_eq!(index.map.get("beatles"), Some(&vec![(0, artist_field)]));
+        assert_eq!(
+            index.stored_fields["0"],
+            json!({"album.artist.name": "The Beatles"})
+        );
+    }
+
+    #[test]
+    fn test_wildcard_fields() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["$.album.tracks[*].title"],
+            "storeFields": [],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        index
+            .add_document(
+                serde_json::from_value(json!({
+                    "id": 1,
+                    "album": {"tracks": [{"title": "Something"}, {"title": "Because"}]},
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let tracks_field = index.field_ids["$.album.tracks[*].title"];
+        assert_eq!(index.map.get("something"), Some(&vec![(0, tracks_field)]));
+        assert_eq!(index.map.get("because"), Some(&vec![(0, tracks_field)]));
+    }
+
+    #[test]
+    fn test_id_field() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "idField": "meta.uuid",
+            "fields": ["title"],
+            "storeFields": [],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        let doc = |value| serde_json::from_value(value).unwrap();
+        index
+            .add_document(doc(json!({"meta": {"uuid": "a1"}, "title": "Help"})))
+            .unwrap();
+        assert_eq!(&index.document_ids, json!({"0": "a1"}).as_object().unwrap());
+        assert!(index
+            .add_document(doc(json!({"id": 1, "title": "Help"})))
+            .is_err());
+        for id in [json!(null), json!([1]), json!({"a": 1})] {
+            let err = index
+                .add_document(doc(json!({"meta": {"uuid": id}, "title": "Help"})))
+                .unwrap_err();
+            assert!(err.to_string().starts_with("invalid id"));
+        }
+        assert_eq!(index.document_count, 1);
+    }
+
+    #[test]
+    fn test_on_duplicate() {
+        let add = |on_duplicate| {
+            let config: IndexConfig = serde_json::from_value(json!({
+                "fields": ["title"],
+                "storeFields": ["title"],
+                "onDuplicate": on_duplicate,
+            }))
+            .unwrap();
+            let mut index = Index::new(config);
+            index
+                .add_document(serde_json::from_value(json!({"id": 1, "title": "Help"})).unwrap())
+                .unwrap();
+            let result = index.add_document(
+                serde_json::from_value(json!({"id": 1, "title": "Yesterday"})).unwrap(),
+            );
+            (index, result)
+        };
+
+        let (_, result) = add("error");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "duplicate id 1, already in the index"
+        );
+
+        let (index, result) = add("keepFirst");
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(index.document_count, 1);
+        assert_eq!(index.map.get("yesterday"), None);
+
+        let (mut index, result) = add("keepLast");
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(index.document_count, 1);
+        assert!(index.search("help", &SearchOptions::default()).is_empty());
+        index.purge_removed();
+        assert_eq!(index.map.get("help"), None);
+        assert_eq!(
+            index.stored_fields,
+            *json!({"1": {"title": "Yesterday"}}).as_object().unwrap()
+        );
+
+        let (mut index, result) = add("allow");
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(index.document_count, 2);
+        index.remove_document(&json!(1)).unwrap();
+        assert_eq!(index.document_count, 0);
+        assert!(index.map.is_empty());
+    }
 }
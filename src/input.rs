@@ -0,0 +1,881 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::debug;
+use serde::Deserialize;
+use serde_json::Value as JSONValue;
+use walkdir::WalkDir;
+
+use crate::errors::MinisearchIndexrsError;
+use crate::index::default_id_field;
+use crate::join::{Join, JoinConfig, UnmatchedKeys};
+use crate::site::{read_page, PAGE_EXTENSIONS};
+use crate::sqlite::{sqlite_documents, SqliteConfig, SQLITE_EXTENSIONS};
+
+pub type Document = HashMap<String, JSONValue>;
+
+type DocumentIterator = Box<dyn Iterator<Item = Result<Document, failure::Error>>>;
+
+/// Where a document was read from: the file, or `<stdin>`, and its position
+/// in it.
+#[derive(Debug, Clone)]
+pub struct Position {
+    pub source: Arc<str>,
+    pub index: usize,
+}
+
+/// Documents read one at a time, keeping count of the bytes read to show
+/// progress.
+pub struct Documents {
+    documents: Box<dyn Iterator<Item = Result<(Position, Document), failure::Error>>>,
+    bytes_read: BytesRead,
+    /* {file joined: keys without a match} */
+    unmatched_keys: Vec<(String, UnmatchedKeys)>,
+    /// The size of the input in bytes, when known.
+    pub len: Option<u64>,
+}
+
+impl Documents {
+    pub fn new<I>(documents: I) -> Self
+    where
+        I: Iterator<Item = Result<(Position, Document), failure::Error>> + 'static,
+    {
+        Documents {
+            documents: Box::new(documents),
+            bytes_read: BytesRead::default(),
+            unmatched_keys: vec![],
+            len: None,
+        }
+    }
+
+    pub fn bytes_read(&self) -> BytesRead {
+        self.bytes_read.clone()
+    }
+
+    /// The keys of each joined file that had no match, filled as the
+    /// documents are read.
+    pub fn unmatched_keys(&self) -> Vec<(String, UnmatchedKeys)> {
+        self.unmatched_keys.clone()
+    }
+}
+
+impl Iterator for Documents {
+    type Item = Result<(Position, Document), failure::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.documents.next()
+    }
+}
+
+/// A shared count of the bytes read from an input.
+#[derive(Clone, Default)]
+pub struct BytesRead(Arc<AtomicU64>);
+
+impl BytesRead {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: BytesRead,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read.0.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// How documents are read, set in the same configuration file as the index.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InputConfig {
+    #[serde(default)]
+    csv: CsvConfig,
+    #[serde(default)]
+    sqlite: SqliteConfig,
+    #[serde(alias = "idField", default = "default_id_field")]
+    id_field: String,
+    #[serde(default)]
+    joins: Vec<JoinConfig>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            csv: CsvConfig::default(),
+            sqlite: SqliteConfig::default(),
+            id_field: default_id_field(),
+            joins: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CsvConfig {
+    /* defaults to `,` for .csv files and to a tab for .tsv files */
+    delimiter: Option<char>,
+    #[serde(default = "default_quote")]
+    quote: char,
+    #[serde(default = "default_quoting")]
+    quoting: bool,
+    /* {column: type}, columns are strings by default */
+    #[serde(default)]
+    columns: HashMap<String, ColumnType>,
+}
+
+impl Default for CsvConfig {
+    fn default() -> Self {
+        CsvConfig {
+            delimiter: None,
+            quote: default_quote(),
+            quoting: default_quoting(),
+            columns: HashMap::new(),
+        }
+    }
+}
+
+fn default_quote() -> char {
+    '"'
+}
+
+fn default_quoting() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ColumnType {
+    String,
+    /// Empty values are null.
+    Number,
+    /// Splits the value, trimming the elements.
+    Array {
+        #[serde(default = "default_separator")]
+        separator: String,
+    },
+}
+
+fn default_separator() -> String {
+    ",".to_owned()
+}
+
+pub fn read_input_config<P: AsRef<Path>>(path: P) -> Result<InputConfig, failure::Error> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+/// A source of documents given in the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+    /// An html or markdown page, which is a single document with `id`.
+    Page {
+        path: PathBuf,
+        id: String,
+    },
+}
+
+const DOCUMENT_EXTENSIONS: &[&str] = &["json", "jsonl", "ndjson", "csv", "tsv"];
+
+/// The extension of a path, lowercased.
+pub(crate) fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/* pages are identified by their path, relative to the walked directory */
+fn file_input(path: PathBuf, id: &Path) -> Input {
+    if PAGE_EXTENSIONS.contains(&extension(&path).as_deref().unwrap_or("")) {
+        let id = id
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        Input::Page { path, id }
+    } else {
+        Input::File(path)
+    }
+}
+
+/// Turns arguments into inputs: `-` is stdin, directories are walked for
+/// files with document or page extensions and arguments with `*`, `?` or `[`
+/// are glob patterns. Files in directories and globs are sorted by path.
+pub fn expand_inputs<S: AsRef<str>>(args: &[S]) -> Result<Vec<Input>, failure::Error> {
+    let mut inputs = vec![];
+    for arg in args.iter().map(|arg| arg.as_ref()) {
+        let path = Path::new(arg);
+        if arg == "-" {
+            inputs.push(Input::Stdin);
+        } else if path.is_dir() {
+            for entry in WalkDir::new(path).sort_by_file_name() {
+                let entry = entry?;
+                let extension = extension(entry.path());
+                let extension = extension.as_deref().unwrap_or("");
+                if entry.file_type().is_file()
+                    && (DOCUMENT_EXTENSIONS.contains(&extension)
+                        || PAGE_EXTENSIONS.contains(&extension))
+                {
+                    let id = entry.path().strip_prefix(path)?.to_path_buf();
+                    inputs.push(file_input(entry.into_path(), &id));
+                }
+            }
+        } else if arg.contains(['*', '?', '[']) {
+            let paths = glob::glob(arg)?.collect::<Result<Vec<_>, _>>()?;
+            if paths.is_empty() {
+                return Err(MinisearchIndexrsError::InvalidInput(
+                    arg.to_owned(),
+                    "no files match the pattern".to_owned(),
+                )
+                .into());
+            }
+            inputs.extend(paths.into_iter().map(|path| {
+                let id = path.clone();
+                file_input(path, &id)
+            }));
+        } else {
+            inputs.push(file_input(path.to_path_buf(), path));
+        }
+    }
+    Ok(inputs)
+}
+
+/// Reads the documents in a file. Files ending in `.csv` or `.tsv` are read
+/// as such, with a header row, and SQLite databases (`.sqlite`, `.sqlite3`
+/// and `.db`) with the configured query. Otherwise they are either a json
+/// array or json lines (a document per line), told apart by the first
+/// character. All of them are read incrementally.
+pub fn read_documents<P: AsRef<Path>>(
+    path: P,
+    config: &InputConfig,
+) -> Result<Documents, failure::Error> {
+    read_inputs(vec![Input::File(path.as_ref().to_path_buf())], config)
+}
+
+/// Reads the documents of several inputs one after the other, opening each
+/// file when its documents are reached. Errors name the file.
+pub fn read_inputs(inputs: Vec<Input>, config: &InputConfig) -> Result<Documents, failure::Error> {
+    let len = inputs
+        .iter()
+        .map(|input| match input {
+            Input::Stdin => None,
+            Input::File(path) | Input::Page { path, .. } => {
+                std::fs::metadata(path).ok().map(|m| m.len())
+            }
+        })
+        .sum::<Option<u64>>();
+    let bytes_read = BytesRead::default();
+    let input_bytes_read = bytes_read.clone();
+    /* the joined files are read with the same configuration, but not joined */
+    let joined_config = InputConfig {
+        joins: vec![],
+        ..config.clone()
+    };
+    let joins = config
+        .joins
+        .iter()
+        .map(|join| Join::load(join, &joined_config))
+        .collect::<Result<Vec<_>, _>>()?;
+    let unmatched_keys = joins
+        .iter()
+        .map(|join| (join.file.clone(), join.unmatched_keys()))
+        .collect();
+    let joins = Arc::new(joins);
+    let config = joined_config;
+    let documents = inputs.into_iter().flat_map(move |input| {
+        let source: Arc<str> = match &input {
+            Input::Stdin => "<stdin>".into(),
+            Input::File(path) | Input::Page { path, .. } => path.to_string_lossy().into(),
+        };
+        let joins = joins.clone();
+        let documents = match open_input(&input, &config, &input_bytes_read) {
+            Ok(documents) => documents,
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        };
+        documents.enumerate().map(move |(index, document)| {
+            document
+                .and_then(|mut document| {
+                    for join in joins.iter() {
+                        join.apply(&mut document).map_err(|e| {
+                            MinisearchIndexrsError::InvalidDocument(index, e.to_string())
+                        })?;
+                    }
+                    Ok(document)
+                })
+                .map(|document| {
+                    let position = Position {
+                        source: source.clone(),
+                        index,
+                    };
+                    (position, document)
+                })
+                .map_err(|e| {
+                    MinisearchIndexrsError::InvalidInput(source.to_string(), e.to_string()).into()
+                })
+        })
+    });
+    Ok(Documents {
+        documents: Box::new(documents),
+        bytes_read,
+        unmatched_keys,
+        len,
+    })
+}
+
+fn open_input(
+    input: &Input,
+    config: &InputConfig,
+    bytes_read: &BytesRead,
+) -> Result<DocumentIterator, failure::Error> {
+    let path = match input {
+        Input::Stdin => {
+            debug!("reading documents from stdin");
+            let reader = CountingReader {
+                inner: io::stdin(),
+                bytes_read: bytes_read.clone(),
+            };
+            return documents_from_reader(BufReader::new(reader));
+        }
+        Input::File(path) => path,
+        Input::Page { path, id } => {
+            debug!("reading page {}", path.to_string_lossy());
+            let document = read_page(path, id, &config.id_field)?;
+            let len = std::fs::metadata(path)?.len();
+            bytes_read.0.fetch_add(len, Ordering::Relaxed);
+            return Ok(Box::new(std::iter::once(Ok(document))));
+        }
+    };
+    debug!("reading documents from {}", path.to_string_lossy());
+    let reader = CountingReader {
+        inner: File::open(path)?,
+        bytes_read: bytes_read.clone(),
+    };
+    let extension = extension(path);
+    if SQLITE_EXTENSIONS.contains(&extension.as_deref().unwrap_or("")) {
+        /* the whole file counts as read when the query ends */
+        let len = reader.inner.metadata()?.len();
+        let bytes_read = bytes_read.clone();
+        let documents = sqlite_documents(path, &config.sqlite, &config.id_field)?;
+        return Ok(Box::new(documents.chain(std::iter::from_fn(move || {
+            bytes_read.0.fetch_add(len, Ordering::Relaxed);
+            None
+        }))));
+    }
+    match extension.as_deref() {
+        Some("csv") => csv_documents(reader, &config.csv, ','),
+        Some("tsv") => csv_documents(reader, &config.csv, '\t'),
+        _ => documents_from_reader(BufReader::new(reader)),
+    }
+}
+
+fn csv_byte(option: &str, c: char) -> Result<u8, MinisearchIndexrsError> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(MinisearchIndexrsError::InvalidConfig(format!(
+            "csv {} must be an ascii character",
+            option
+        )))
+    }
+}
+
+fn csv_documents<R: Read + 'static>(
+    reader: R,
+    config: &CsvConfig,
+    default_delimiter: char,
+) -> Result<DocumentIterator, failure::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(csv_byte(
+            "delimiter",
+            config.delimiter.unwrap_or(default_delimiter),
+        )?)
+        .quote(csv_byte("quote", config.quote)?)
+        .quoting(config.quoting)
+        .from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let columns = headers
+        .iter()
+        .map(|header| {
+            config
+                .columns
+                .get(header)
+                .cloned()
+                .unwrap_or(ColumnType::String)
+        })
+        .collect::<Vec<_>>();
+    Ok(Box::new(reader.into_records().map(move |record| {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        headers
+            .iter()
+            .zip(columns.iter())
+            .zip(record.iter())
+            .map(|((header, column), value)| {
+                let value = column_value(column, value).ok_or_else(|| {
+                    MinisearchIndexrsError::InvalidLine(
+                        line,
+                        format!("column {}: {} is not a number", header, value),
+                    )
+                })?;
+                Ok((header.to_owned(), value))
+            })
+            .collect()
+    })))
+}
+
+fn column_value(column: &ColumnType, value: &str) -> Option<JSONValue> {
+    Some(match column {
+        ColumnType::String => value.into(),
+        ColumnType::Number if value.trim().is_empty() => JSONValue::Null,
+        ColumnType::Number => match value.trim().parse::<i64>() {
+            Ok(n) => n.into(),
+            Err(_) => serde_json::Number::from_f64(value.trim().parse().ok()?)?.into(),
+        },
+        ColumnType::Array { .. } if value.trim().is_empty() => JSONValue::Array(vec![]),
+        ColumnType::Array { separator } => value
+            .split(separator.as_str())
+            .map(|element| element.trim().into())
+            .collect::<Vec<JSONValue>>()
+            .into(),
+    })
+}
+
+fn documents_from_reader<R>(mut reader: R) -> Result<DocumentIterator, failure::Error>
+where
+    R: BufRead + 'static,
+{
+    let (first, skipped_lines) = first_byte(&mut reader)?;
+    Ok(match first {
+        Some(b'[') => Box::new(json_array(reader, skipped_lines)),
+        _ => Box::new(json_lines(reader, skipped_lines)),
+    })
+}
+
+/* counts the lines and columns consumed like serde_json does, to place the
+ * errors of each element in the whole input */
+struct PositionReader<R> {
+    inner: R,
+    line: usize,
+    column: usize,
+}
+
+fn advance(line: &mut usize, column: &mut usize, bytes: &[u8]) {
+    for b in bytes {
+        if *b == b'\n' {
+            *line += 1;
+            *column = 0;
+        } else {
+            *column += 1;
+        }
+    }
+}
+
+impl<R: BufRead> PositionReader<R> {
+    /* the next byte that is not whitespace, which is left unread */
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        let PositionReader {
+            inner,
+            line,
+            column,
+        } = self;
+        loop {
+            let buf = inner.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            let whitespace = buf
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .unwrap_or(buf.len());
+            let next = buf.get(whitespace).copied();
+            advance(line, column, &buf[..whitespace]);
+            inner.consume(whitespace);
+            if next.is_some() {
+                return Ok(next);
+            }
+        }
+    }
+
+    fn consume(&mut self) {
+        advance(&mut self.line, &mut self.column, b" ");
+        self.inner.consume(1);
+    }
+
+    /* an error at the next byte, formatted like serde_json ones */
+    fn error(&self, message: &str) -> String {
+        format!(
+            "{} at line {} column {}",
+            message,
+            self.line,
+            self.column + 1
+        )
+    }
+}
+
+impl<R: Read> Read for PositionReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        advance(&mut self.line, &mut self.column, &buf[..n]);
+        Ok(n)
+    }
+}
+
+/* Parses the elements of a json array one at a time, each with its own
+ * deserializer, since serde_json cannot pause a sequence. Objects end with
+ * `}`, so the deserializer reads nothing past them. */
+struct JsonArray<R> {
+    reader: PositionReader<R>,
+    index: usize,
+    done: bool,
+}
+
+impl<R: BufRead> JsonArray<R> {
+    fn next_document(&mut self) -> Result<Option<Document>, failure::Error> {
+        let invalid = |index, message| MinisearchIndexrsError::InvalidDocument(index, message);
+        if self.index == 0 {
+            /* the `[` found by `first_byte` */
+            self.reader.peek()?;
+            self.reader.consume();
+        }
+        match (self.reader.peek()?, self.index) {
+            (Some(b']'), _) => {
+                self.reader.consume();
+                return match self.reader.peek()? {
+                    None => Ok(None),
+                    Some(_) => Err(failure::err_msg(self.reader.error("trailing characters"))),
+                };
+            }
+            (Some(b','), index) if index > 0 => {
+                self.reader.consume();
+                if self.reader.peek()? == Some(b']') {
+                    return Err(invalid(index, self.reader.error("trailing comma")).into());
+                }
+            }
+            (None, index) => {
+                return Err(invalid(index, self.reader.error("EOF while parsing a list")).into())
+            }
+            (Some(_), 0) => (),
+            (Some(_), index) => {
+                return Err(invalid(index, self.reader.error("expected `,` or `]`")).into())
+            }
+        }
+        let (line, column) = (self.reader.line, self.reader.column);
+        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.reader);
+        Document::deserialize(&mut deserializer)
+            .map(Some)
+            .map_err(|e| invalid(self.index, element_error(e, line, column)).into())
+    }
+}
+
+/* moves the position of an error in an element to the whole input */
+fn element_error(e: serde_json::Error, line: usize, column: usize) -> String {
+    let message = e.to_string();
+    let position = format!(" at line {} column {}", e.line(), e.column());
+    match message.strip_suffix(&position) {
+        Some(message) if e.line() > 0 => {
+            let (line, column) = if e.line() == 1 {
+                (line, column + e.column())
+            } else {
+                (line + e.line() - 1, e.column())
+            };
+            format!("{} at line {} column {}", message, line, column)
+        }
+        _ => message,
+    }
+}
+
+impl<R: BufRead> Iterator for JsonArray<R> {
+    type Item = Result<Document, failure::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let document = self.next_document();
+        self.index += 1;
+        match document {
+            Ok(Some(document)) => Some(Ok(document)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn json_array<R: BufRead>(
+    reader: R,
+    skipped_lines: usize,
+) -> impl Iterator<Item = Result<Document, failure::Error>> {
+    JsonArray {
+        reader: PositionReader {
+            inner: reader,
+            line: skipped_lines + 1,
+            column: 0,
+        },
+        index: 0,
+        done: false,
+    }
+}
+
+/* the first non whitespace byte, consuming only whole buffers of whitespace */
+fn first_byte<R: BufRead>(reader: &mut R) -> io::Result<(Option<u8>, usize)> {
+    let mut skipped_lines = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok((None, skipped_lines));
+        }
+        if let Some(i) = buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            return Ok((Some(buf[i]), skipped_lines));
+        }
+        let len = buf.len();
+        skipped_lines += buf.iter().filter(|b| **b == b'\n').count();
+        reader.consume(len);
+    }
+}
+
+/* blank lines are skipped, and errors name the line */
+fn json_lines<R: BufRead>(
+    reader: R,
+    skipped_lines: usize,
+) -> impl Iterator<Item = Result<Document, failure::Error>> {
+    reader.lines().enumerate().filter_map(move |(i, line)| {
+        let line_number = skipped_lines + i + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(
+            serde_json::from_str(&line).map_err(|e| {
+                MinisearchIndexrsError::InvalidLine(line_number, e.to_string()).into()
+            }),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    fn read(data: &'static str) -> Vec<Result<Document, failure::Error>> {
+        documents_from_reader(Cursor::new(data)).unwrap().collect()
+    }
+
+    #[test]
+    fn test_json_array() {
+        let documents = read(" \n[{\"id\": 1}, {\"id\": 2}]");
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].as_ref().unwrap()["id"], json!(2));
+
+        let documents = read("[\n{\"id\": 1},\n{\"id\": 2,}\n]");
+        assert_eq!(documents.len(), 2);
+        assert_eq!(
+            documents[1].as_ref().unwrap_err().to_string(),
+            "document at index 1: trailing comma at line 3 column 10"
+        );
+        let documents = read("[{\"id\": 1}] x");
+        assert_eq!(
+            documents[1].as_ref().unwrap_err().to_string(),
+            "trailing characters at line 1 column 13"
+        );
+        assert!(read(" [ ] ").is_empty());
+        let documents = read("[{\"id\": 1} {\"id\": 2}]");
+        assert_eq!(
+            documents[1].as_ref().unwrap_err().to_string(),
+            "document at index 1: expected `,` or `]` at line 1 column 12"
+        );
+        let documents = read("[{\"id\": 1}, {\n\"id\": x}");
+        assert_eq!(
+            documents[1].as_ref().unwrap_err().to_string(),
+            "document at index 1: expected value at line 2 column 7"
+        );
+        let documents = read("[{\"id\": 1},");
+        assert_eq!(
+            documents[1].as_ref().unwrap_err().to_string(),
+            "document at index 1: EOF while parsing a value at line 1 column 11"
+        );
+    }
+
+    fn read_csv(
+        data: &'static str,
+        config: serde_json::Value,
+        delimiter: char,
+    ) -> Vec<Result<Document, failure::Error>> {
+        let config = serde_json::from_value(config).unwrap();
+        csv_documents(Cursor::new(data), &config, delimiter)
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn test_csv() {
+        let documents = read_csv(
+            "id,title,year,tags\n1,\"Help, I need\",1965,rock| pop\n2,Hey Jude,,\n3,x,y,\n",
+            json!({"columns": {"year": {"type": "number"}, "tags": {"type": "array", "separator": "|"}}}),
+            ',',
+        );
+        assert_eq!(
+            serde_json::to_value(documents[0].as_ref().unwrap()).unwrap(),
+            json!({"id": "1", "title": "Help, I need", "year": 1965, "tags": ["rock", "pop"]})
+        );
+        assert_eq!(
+            serde_json::to_value(documents[1].as_ref().unwrap()).unwrap(),
+            json!({"id": "2", "title": "Hey Jude", "year": null, "tags": []})
+        );
+        assert_eq!(
+            documents[2].as_ref().unwrap_err().to_string(),
+            "line 4: column year: y is not a number"
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        let documents = read_csv(
+            "id\ttitle\n1\t'Help\tme'\n",
+            json!({"quote": "'", "columns": {"id": {"type": "number"}}}),
+            '\t',
+        );
+        assert_eq!(
+            serde_json::to_value(documents[0].as_ref().unwrap()).unwrap(),
+            json!({"id": 1, "title": "Help\tme"})
+        );
+        let documents = read_csv(
+            "id;title\n1;\"a\"\n",
+            json!({"delimiter": ";", "quoting": false}),
+            ',',
+        );
+        assert_eq!(documents[0].as_ref().unwrap()["title"], json!("\"a\""));
+        assert!(
+            serde_json::from_value::<CsvConfig>(json!({"delimiter": "é"}))
+                .map_err(failure::Error::from)
+                .and_then(|config| csv_documents(Cursor::new(""), &config, ','))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_bytes_read() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"[{\"id\": 1}, {\"id\": 2}]").unwrap();
+        let mut documents = read_documents(file.path(), &InputConfig::default()).unwrap();
+        assert_eq!(documents.len, Some(22));
+        let bytes_read = documents.bytes_read();
+        assert!(documents.next().unwrap().is_ok());
+        assert_eq!(bytes_read.get(), 22);
+        assert_eq!(documents.count(), 1);
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let documents =
+            read("\n{\"id\": 1}\n\n{\"id\": 2, \"a\": \"x\"}\r\n{\"id\": \n{\"id\": 4}");
+        assert_eq!(documents.len(), 4);
+        assert_eq!(documents[1].as_ref().unwrap()["a"], json!("x"));
+        assert_eq!(
+            documents[2].as_ref().unwrap_err().to_string(),
+            "line 5: EOF while parsing a value at line 1 column 7"
+        );
+        assert_eq!(documents[3].as_ref().unwrap()["id"], json!(4));
+        assert!(read("").is_empty());
+    }
+
+    #[test]
+    fn test_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, data: &str| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        let a = write("a.json", r#"[{"id": 1}, {"id": 2}]"#);
+        let b = write("shard/b.jsonl", "{\"id\": 3}\n");
+        let c = write("shard/c.csv", "id\n4\n");
+        write("shard/notes.txt", "not documents");
+        let bad = write("bad/d.jsonl", "{\"id\": 5}\n{\n");
+
+        let pattern = dir.path().join("*.json").to_string_lossy().into_owned();
+        let shard = dir.path().join("shard").to_string_lossy().into_owned();
+        let inputs = expand_inputs(&[pattern.as_str(), shard.as_str(), "-"]).unwrap();
+        assert_eq!(
+            inputs,
+            vec![
+                Input::File(a.clone()),
+                Input::File(b.clone()),
+                Input::File(c.clone()),
+                Input::Stdin
+            ]
+        );
+        let missing = dir.path().join("*.tsv").to_string_lossy().into_owned();
+        assert!(expand_inputs(&[missing]).is_err());
+
+        let inputs = vec![
+            Input::File(a),
+            Input::File(c.clone()),
+            Input::File(bad.clone()),
+        ];
+        let documents = read_inputs(inputs, &InputConfig::default()).unwrap();
+        assert_eq!(documents.len.unwrap(), 22 + 5 + 12);
+        let documents = documents.collect::<Vec<_>>();
+        let (position, document) = documents[2].as_ref().unwrap();
+        assert_eq!(&*position.source, c.to_string_lossy());
+        assert_eq!(position.index, 0);
+        assert_eq!(document["id"], json!("4"));
+        assert_eq!(documents[3].as_ref().unwrap().1["id"], json!(5));
+        assert_eq!(
+            documents[4].as_ref().unwrap_err().to_string(),
+            format!(
+                "{}: line 2: EOF while parsing an object at line 1 column 1",
+                bad.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir_all(site.join("songs")).unwrap();
+        std::fs::write(site.join("index.html"), "<title>Songs</title>").unwrap();
+        std::fs::write(site.join("songs/help.md"), "# Help\n").unwrap();
+
+        let inputs = expand_inputs(&[site.to_string_lossy()]).unwrap();
+        assert_eq!(
+            inputs,
+            vec![
+                Input::Page {
+                    path: site.join("index.html"),
+                    id: "index.html".to_owned()
+                },
+                Input::Page {
+                    path: site.join("songs/help.md"),
+                    id: "songs/help.md".to_owned()
+                },
+            ]
+        );
+        let mut documents = read_inputs(inputs, &InputConfig::default()).unwrap();
+        let bytes_read = documents.bytes_read();
+        let (position, document) = documents.nth(1).unwrap().unwrap();
+        assert_eq!(position.index, 0);
+        assert_eq!(document["id"], json!("songs/help.md"));
+        assert_eq!(document["title"], json!("Help"));
+        assert_eq!(bytes_read.get(), 20 + 7);
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{Map as JSONMap, Value as JSONValue};
+
+use crate::document::{extract_field, js_string};
+use crate::errors::MinisearchIndexrsError;
+use crate::input::{expand_inputs, read_inputs, Document, InputConfig};
+
+/// Merges into each document the record of another data file whose
+/// `foreignKey` (`key` by default) is the document `key`. The fields of the
+/// record are added with `prefix`, or the record is put under `field`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct JoinConfig {
+    file: String,
+    key: String,
+    #[serde(alias = "foreignKey")]
+    foreign_key: Option<String>,
+    prefix: Option<String>,
+    field: Option<String>,
+}
+
+/* distinct unmatched keys are kept up to this many, to bound the memory */
+const UNMATCHED_KEYS_LIMIT: usize = 10_000;
+
+/// The keys without a matching record, shared to report them once the
+/// documents are read. Only the first distinct keys are kept, the rest are
+/// counted as documents.
+#[derive(Clone, Default)]
+pub struct UnmatchedKeys(Arc<Mutex<Unmatched>>);
+
+#[derive(Default)]
+struct Unmatched {
+    keys: HashSet<String>,
+    documents: usize,
+}
+
+impl UnmatchedKeys {
+    fn insert(&self, key: String) {
+        let mut unmatched = self.0.lock().unwrap();
+        unmatched.documents += 1;
+        if unmatched.keys.len() < UNMATCHED_KEYS_LIMIT {
+            unmatched.keys.insert(key);
+        }
+    }
+
+    /// The distinct keys, which is a lower bound when the limit was hit.
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `len` counts all the distinct keys.
+    pub fn is_exact(&self) -> bool {
+        self.len() < UNMATCHED_KEYS_LIMIT
+    }
+
+    /// The documents whose key had no match.
+    pub fn documents(&self) -> usize {
+        self.0.lock().unwrap().documents
+    }
+}
+
+enum Merge {
+    Prefix(String),
+    Field(String),
+}
+
+pub struct Join {
+    pub file: String,
+    key: String,
+    merge: Merge,
+    /* {key: record} */
+    records: HashMap<String, Document>,
+    unmatched: UnmatchedKeys,
+}
+
+/* like javascript property keys, so `1` matches `"1"` from a csv file */
+fn key_string(value: &JSONValue) -> Option<String> {
+    match value {
+        JSONValue::Null | JSONValue::Array(_) | JSONValue::Object(_) => None,
+        value => Some(js_string(value)),
+    }
+}
+
+/* sets a dotted path, creating the missing objects on the way; other
+ * values on the way are not replaced, but null ones */
+fn insert_nested(
+    document: &mut Document,
+    field: &str,
+    value: JSONValue,
+) -> Result<(), MinisearchIndexrsError> {
+    let mut keys = field.split('.');
+    let first = keys.next().unwrap_or(field);
+    let mut keys = keys.peekable();
+    if keys.peek().is_none() {
+        document.insert(first.to_owned(), value);
+        return Ok(());
+    }
+    let mut path = first.to_owned();
+    let mut current = document.entry(first.to_owned()).or_insert(JSONValue::Null);
+    while let Some(key) = keys.next() {
+        if current.is_null() {
+            *current = JSONValue::Object(JSONMap::new());
+        }
+        let object = current.as_object_mut().ok_or_else(|| {
+            MinisearchIndexrsError::InvalidInput(path.clone(), "not an object".to_owned())
+        })?;
+        if keys.peek().is_none() {
+            object.insert(key.to_owned(), value);
+            return Ok(());
+        }
+        path = format!("{}.{}", path, key);
+        current = object.entry(key.to_owned()).or_insert(JSONValue::Null);
+    }
+    Ok(())
+}
+
+/* removes a field found like `extract_field` does, as a top level key or a
+ * dotted path */
+fn remove_nested(document: &mut Document, field: &str) {
+    if document.remove(field).is_some() || !field.contains('.') {
+        return;
+    }
+    let mut keys = field.split('.').collect::<Vec<_>>();
+    let last = keys.pop().unwrap_or(field);
+    let mut current = match document.get_mut(keys[0]) {
+        Some(value) => value,
+        None => return,
+    };
+    for key in keys[1..].iter() {
+        current = match current.get_mut(*key) {
+            Some(value) => value,
+            None => return,
+        };
+    }
+    if let Some(object) = current.as_object_mut() {
+        object.remove(last);
+    }
+}
+
+impl Join {
+    /// Reads all the records of the file, which can be in any of the input
+    /// formats.
+    pub fn load(config: &JoinConfig, input_config: &InputConfig) -> Result<Self, failure::Error> {
+        let merge = match (&config.prefix, &config.field) {
+            (Some(prefix), None) => Merge::Prefix(prefix.clone()),
+            (None, Some(field)) => Merge::Field(field.clone()),
+            _ => {
+                return Err(MinisearchIndexrsError::InvalidConfig(format!(
+                    "the join with {} needs either a prefix or a field",
+                    config.file
+                ))
+                .into())
+            }
+        };
+        let foreign_key = config.foreign_key.as_deref().unwrap_or(&config.key);
+        debug!("reading records to join from {}", config.file);
+        let mut records = HashMap::new();
+        for record in read_inputs(expand_inputs(&[&config.file])?, input_config)? {
+            let (position, mut record) = record?;
+            let key = match extract_field(&record, foreign_key).and_then(|k| key_string(&k)) {
+                Some(key) => key,
+                None => {
+                    return Err(MinisearchIndexrsError::InvalidInput(
+                        position.source.to_string(),
+                        MinisearchIndexrsError::InvalidDocument(
+                            position.index,
+                            format!("no {} to join by", foreign_key),
+                        )
+                        .to_string(),
+                    )
+                    .into())
+                }
+            };
+            /* the foreign key is the document key already */
+            remove_nested(&mut record, foreign_key);
+            match records.entry(key) {
+                Entry::Occupied(entry) => {
+                    warn!(
+                        "{}: ignoring another record with {}",
+                        config.file,
+                        entry.key()
+                    )
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(record);
+                }
+            }
+        }
+        Ok(Join {
+            file: config.file.clone(),
+            key: config.key.clone(),
+            merge,
+            records,
+            unmatched: UnmatchedKeys::default(),
+        })
+    }
+
+    pub fn unmatched_keys(&self) -> UnmatchedKeys {
+        self.unmatched.clone()
+    }
+
+    /// Merges the matching record. Documents without the key are left as
+    /// they are. Fails if a value on the way to `field` is not an object.
+    pub fn apply(&self, document: &mut Document) -> Result<(), MinisearchIndexrsError> {
+        let key = match extract_field(document, &self.key).and_then(|k| key_string(&k)) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let record = match self.records.get(&key) {
+            Some(record) => record,
+            None => {
+                self.unmatched.insert(key);
+                return Ok(());
+            }
+        };
+        let fields = record
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()));
+        match &self.merge {
+            Merge::Prefix(prefix) => {
+                for (name, value) in fields {
+                    document.insert(format!("{}{}", prefix, name), value);
+                }
+            }
+            Merge::Field(field) => {
+                insert_nested(document, field, JSONValue::Object(fields.collect()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document(value: JSONValue) -> Document {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_join() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("artists.csv");
+        std::fs::write(
+            &path,
+            "id,name,genre\n1,The Beatles,rock\n2,Miles Davis,jazz\n",
+        )
+        .unwrap();
+        let file = path.to_string_lossy().into_owned();
+
+        let config: JoinConfig = serde_json::from_value(json!({
+            "file": file,
+            "key": "artist_id",
+            "foreignKey": "id",
+            "prefix": "artist_",
+        }))
+        .unwrap();
+        let join = Join::load(&config, &InputConfig::default()).unwrap();
+        let mut song = document(json!({"id": "help", "artist_id": 1}));
+        join.apply(&mut song).unwrap();
+        assert_eq!(
+            song,
+            document(json!({
+                "id": "help",
+                "artist_id": 1,
+                "artist_name": "The Beatles",
+                "artist_genre": "rock",
+            }))
+        );
+
+        let unmatched = join.unmatched_keys();
+        for artist_id in [json!(3), json!("3"), json!(4)] {
+            join.apply(&mut document(json!({"id": "x", "artist_id": artist_id})))
+                .unwrap();
+        }
+        let mut untouched = document(json!({"id": "y"}));
+        join.apply(&mut untouched).unwrap();
+        assert_eq!(untouched, document(json!({"id": "y"})));
+        assert_eq!(unmatched.len(), 2);
+        assert_eq!(unmatched.documents(), 3);
+        assert!(unmatched.is_exact());
+
+        let config: JoinConfig = serde_json::from_value(json!({
+            "file": file,
+            "key": "meta.artist",
+            "foreignKey": "id",
+            "field": "meta.artist",
+        }))
+        .unwrap();
+        let join = Join::load(&config, &InputConfig::default()).unwrap();
+        let mut song = document(json!({"id": "so what", "meta": {"artist": "2"}}));
+        join.apply(&mut song).unwrap();
+        assert_eq!(
+            song,
+            document(json!({
+                "id": "so what",
+                "meta": {"artist": {"name": "Miles Davis", "genre": "jazz"}},
+            }))
+        );
+        let config: JoinConfig = serde_json::from_value(json!({
+            "file": file,
+            "key": "artist_id",
+            "foreignKey": "id",
+            "field": "meta.artist",
+        }))
+        .unwrap();
+        let join = Join::load(&config, &InputConfig::default()).unwrap();
+        let mut song = document(json!({"id": "help", "artist_id": 1, "meta": "1965"}));
+        assert_eq!(
+            join.apply(&mut song).unwrap_err().to_string(),
+            "meta: not an object"
+        );
+        assert_eq!(song["meta"], json!("1965"));
+
+        let config: JoinConfig =
+            serde_json::from_value(json!({"file": file, "key": "artist_id"})).unwrap();
+        assert!(Join::load(&config, &InputConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_nested_foreign_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("artists.json");
+        std::fs::write(
+            &path,
+            r#"[{"name": "The Beatles", "meta": {"id": 1, "label": "Apple"}}]"#,
+        )
+        .unwrap();
+        let config: JoinConfig = serde_json::from_value(json!({
+            "file": path.to_string_lossy(),
+            "key": "artist_id",
+            "foreignKey": "meta.id",
+            "prefix": "artist_",
+        }))
+        .unwrap();
+        let join = Join::load(&config, &InputConfig::default()).unwrap();
+        let mut song = document(json!({"id": "help", "artist_id": 1}));
+        join.apply(&mut song).unwrap();
+        assert_eq!(
+            song,
+            document(json!({
+                "id": "help",
+                "artist_id": 1,
+                "artist_name": "The Beatles",
+                "artist_meta": {"label": "Apple"},
+            }))
+        );
+    }
+}
@@ -0,0 +1,11 @@
+pub mod document;
+pub mod errors;
+pub mod index;
+pub mod input;
+pub mod join;
+pub mod search;
+pub mod serializer;
+pub mod site;
+pub mod sqlite;
+pub mod term_processor;
+pub mod tokenizer;
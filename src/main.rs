@@ -1,140 +1,300 @@
-use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
 use std::io::Write;
 use std::path::Path;
 use std::process;
+use std::sync::Arc;
 
 use indicatif::ProgressBar;
-use lazy_static::lazy_static;
-use log::{debug, warn};
-use regex::Regex;
+use log::debug;
 use serde_json::Value as JSONValue;
 use structopt::StructOpt;
 
-mod errors;
-mod index;
-mod serializer;
+use minisearch_indexrs::{errors, index, input, search, serializer};
 
-fn tokenize(text: &str) -> impl Iterator<Item = &str> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"[\n\r -#%-*,-/:;?@\[-\]_{}\u00A0\u00A1\u00A7\u00AB\u00B6\u00B7\u00BB\u00BF\u037E\u0387\u055A-\u055F\u0589\u058A\u05BE\u05C0\u05C3\u05C6\u05F3\u05F4\u0609\u060A\u060C\u060D\u061B\u061E\u061F\u066A-\u066D\u06D4\u0700-\u070D\u07F7-\u07F9\u0830-\u083E\u085E\u0964\u0965\u0970\u09FD\u0A76\u0AF0\u0C77\u0C84\u0DF4\u0E4F\u0E5A\u0E5B\u0F04-\u0F12\u0F14\u0F3A-\u0F3D\u0F85\u0FD0-\u0FD4\u0FD9\u0FDA\u104A-\u104F\u10FB\u1360-\u1368\u1400\u166E\u1680\u169B\u169C\u16EB-\u16ED\u1735\u1736\u17D4-\u17D6\u17D8-\u17DA\u1800-\u180A\u1944\u1945\u1A1E\u1A1F\u1AA0-\u1AA6\u1AA8-\u1AAD\u1B5A-\u1B60\u1BFC-\u1BFF\u1C3B-\u1C3F\u1C7E\u1C7F\u1CC0-\u1CC7\u1CD3\u2000-\u200A\u2010-\u2029\u202F-\u2043\u2045-\u2051\u2053-\u205F\u207D\u207E\u208D\u208E\u2308-\u230B\u2329\u232A\u2768-\u2775\u27C5\u27C6\u27E6-\u27EF\u2983-\u2998\u29D8-\u29DB\u29FC\u29FD\u2CF9-\u2CFC\u2CFE\u2CFF\u2D70\u2E00-\u2E2E\u2E30-\u2E4F\u3000-\u3003\u3008-\u3011\u3014-\u301F\u3030\u303D\u30A0\u30FB\uA4FE\uA4FF\uA60D-\uA60F\uA673\uA67E\uA6F2-\uA6F7\uA874-\uA877\uA8CE\uA8CF\uA8F8-\uA8FA\uA8FC\uA92E\uA92F\uA95F\uA9C1-\uA9CD\uA9DE\uA9DF\uAA5C-\uAA5F\uAADE\uAADF\uAAF0\uAAF1\uABEB\uFD3E\uFD3F\uFE10-\uFE19\uFE30-\uFE52\uFE54-\uFE61\uFE63\uFE68\uFE6A\uFE6B\uFF01-\uFF03\uFF05-\uFF0A\uFF0C-\uFF0F\uFF1A\uFF1B\uFF1F\uFF20\uFF3B-\uFF3D\uFF3F\uFF5B\uFF5D\uFF5F-\uFF65]+").unwrap();
-    }
-    RE.split(text)
-}
-
-fn get_document_tokens(
-    field_ids: &HashMap<String, usize>,
-    document: &HashMap<String, String>,
-    document_id: usize,
-) -> Vec<(String, usize, usize)> {
-    let default = &"".to_owned();
-    field_ids
-        .iter()
-        .flat_map(|(field_name, field_id)| {
-            let text = document.get(field_name).unwrap_or(default);
-            let tokens = tokenize(text);
-            tokens.map(|x| (x.to_owned(), *field_id, document_id.to_owned()))
-        })
-        .collect()
+fn create_index(
+    docs: input::Documents,
+    config: index::IndexConfig,
+    progress: Option<&ProgressBar>,
+) -> Result<index::Index, failure::Error> {
+    let mut index = index::Index::new(config);
+    add_documents(&mut index, docs, progress)?;
+    Ok(index)
 }
 
-fn json_document_to_text_document(
-    json_document: &HashMap<String, JSONValue>,
-    fields: &HashSet<String>,
-) -> HashMap<String, String> {
-    json_document
-        .iter()
-        .filter_map(|(k, v)| {
-            if k != "id" && !fields.contains(k) {
-                return None;
-            }
-            match v {
-                JSONValue::Null => Some((k.clone(), "".to_owned())),
-                JSONValue::Number(ref n) => Some((k.clone(), n.to_string())),
-                JSONValue::String(ref s) => Some((k.clone(), s.clone())),
-                _ => {
-                    warn!("unsupported type for field {}", k);
-                    None
+fn add_documents(
+    index: &mut index::Index,
+    docs: input::Documents,
+    progress: Option<&ProgressBar>,
+) -> Result<(), failure::Error> {
+    /* [(index in sources, index in the source)] by documentId, from the
+     * first one added, to report duplicates, which only fail with
+     * `onDuplicate: error`; the documentIds are consecutive then */
+    let track_positions = index.on_duplicate() == index::OnDuplicate::Error;
+    let mut first_small_id = None;
+    let mut positions: Vec<(usize, usize)> = vec![];
+    let mut sources: Vec<Arc<str>> = vec![];
+    let bytes_read = docs.bytes_read();
+    let unmatched_keys = docs.unmatched_keys();
+    for doc in docs {
+        let (position, doc) = doc?;
+        if let Some(p) = progress {
+            p.set_position(bytes_read.get());
+        }
+        match index.add_document(doc) {
+            Ok(small_id) if track_positions => {
+                if !sources
+                    .last()
+                    .is_some_and(|source| Arc::ptr_eq(source, &position.source))
+                {
+                    sources.push(position.source.clone());
                 }
+                first_small_id.get_or_insert(small_id);
+                positions.push((sources.len() - 1, position.index));
             }
-        })
-        .collect()
+            Ok(_) => (),
+            Err(e) => {
+                let first = match e.downcast_ref() {
+                    Some(errors::MinisearchIndexrsError::DuplicateId(id, small_id)) => {
+                        first_small_id
+                            .and_then(|first_small_id| small_id.checked_sub(first_small_id))
+                            .and_then(|i| positions.get(i))
+                            .map(|position| (id, position))
+                    }
+                    _ => None,
+                };
+                let error = match first {
+                    Some((id, &(source, first))) => {
+                        errors::MinisearchIndexrsError::DuplicateDocument(
+                            position.index,
+                            id.clone(),
+                            first,
+                            sources[source].to_string(),
+                        )
+                    }
+                    None => errors::MinisearchIndexrsError::InvalidDocument(
+                        position.index,
+                        e.to_string(),
+                    ),
+                };
+                return Err(errors::MinisearchIndexrsError::InvalidInput(
+                    position.source.to_string(),
+                    error.to_string(),
+                )
+                .into());
+            }
+        }
+    }
+    for (file, keys) in unmatched_keys.iter().filter(|(_, keys)| !keys.is_empty()) {
+        eprintln!(
+            "{}: {}{} keys had no match, in {} documents",
+            file,
+            if keys.is_exact() { "" } else { "at least " },
+            keys.len(),
+            keys.documents()
+        );
+    }
+    Ok(())
 }
 
-fn get_path_documents<P: AsRef<Path>>(
-    path: P,
-) -> Result<Vec<HashMap<String, JSONValue>>, failure::Error> {
-    debug!("reading documents from {}", path.as_ref().to_string_lossy());
+/* in bytes, unless reading from stdin */
+fn progress_bar(docs: &input::Documents) -> ProgressBar {
+    match docs.len {
+        Some(len) => ProgressBar::new(len),
+        None => ProgressBar::new_spinner(),
+    }
+}
+
+fn get_path_ids<P: AsRef<Path>>(path: P) -> Result<Vec<JSONValue>, failure::Error> {
+    debug!("reading ids from {}", path.as_ref().to_string_lossy());
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     Ok(serde_json::from_reader(reader)?)
 }
 
-fn create_index(
-    docs: Vec<HashMap<String, JSONValue>>,
-    config: index::IndexConfig,
-    progress: Option<&ProgressBar>,
-) -> Result<index::Index, failure::Error> {
-    let mut index = index::Index::new(config);
-    let field_ids = index.field_ids();
-    let fields = field_ids.keys().cloned().collect();
-
-    let docs = docs
-        .into_iter()
-        .map(|mut d| {
-            let small_id = index.insert_document(
-                d.remove("id")
-                    .ok_or(errors::MinisearchIndexrsError::MissingId)?,
-            );
-            Ok((small_id, d))
-        })
-        .collect::<Result<Vec<_>, failure::Error>>()?;
-
-    index.add_document_tokens(docs.iter().flat_map(|(small_id, doc)| {
-        if let Some(p) = progress {
-            p.inc(1);
-        }
-        let doc = json_document_to_text_document(doc, &fields);
-        get_document_tokens(&field_ids, &doc, *small_id)
-    }))?;
-    index.add_document_fields(docs.into_iter());
-    Ok(index)
-}
-
 #[derive(StructOpt)]
 struct Cli {
     #[structopt(parse(from_os_str))]
-    config_path: std::path::PathBuf,
-    #[structopt(parse(from_os_str))]
-    data_path: std::path::PathBuf,
-    #[structopt(default_value = "0")]
+    config_path: Option<std::path::PathBuf>,
+    /// Files, directories or glob patterns with the documents, or `-` for
+    /// stdin
+    data_paths: Vec<String>,
+    /// Builds the index this many times without writing it
+    #[structopt(long, default_value = "0")]
     benchmark: usize,
+    /// Serialization format of the index: `legacy` (MiniSearch 3) or `v2`
+    #[structopt(long, global = true, default_value = "legacy")]
+    format: serializer::Format,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Adds the documents in data files to an existing index
+    Add {
+        #[structopt(parse(from_os_str))]
+        config_path: std::path::PathBuf,
+        #[structopt(parse(from_os_str))]
+        index_path: std::path::PathBuf,
+        #[structopt(required = true)]
+        data_paths: Vec<String>,
+    },
+    /// Removes the documents whose ids are listed in a json array from an
+    /// existing index
+    Remove {
+        #[structopt(parse(from_os_str))]
+        config_path: std::path::PathBuf,
+        #[structopt(parse(from_os_str))]
+        index_path: std::path::PathBuf,
+        #[structopt(parse(from_os_str))]
+        ids_path: std::path::PathBuf,
+    },
+    /// Searches an existing index using the `searchOptions` in the
+    /// configuration
+    Search {
+        #[structopt(parse(from_os_str))]
+        config_path: std::path::PathBuf,
+        #[structopt(parse(from_os_str))]
+        index_path: std::path::PathBuf,
+        query: String,
+        /// Parses the query as a json query tree
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Suggests completions for partial queries using the
+    /// `autoSuggestOptions` in the configuration. Prints a line per query.
+    Suggest {
+        #[structopt(parse(from_os_str))]
+        config_path: std::path::PathBuf,
+        #[structopt(parse(from_os_str))]
+        index_path: std::path::PathBuf,
+        #[structopt(required = true)]
+        queries: Vec<String>,
+    },
+}
+
+/* `<config_path> <data_path> <n>`, the benchmark before `--benchmark`, when
+ * `n` is not a file */
+fn trailing_benchmark(data_paths: &mut Vec<String>) -> Option<usize> {
+    match data_paths.last() {
+        Some(last) if data_paths.len() > 1 && !Path::new(last).exists() => {
+            let benchmark = last.parse().ok()?;
+            data_paths.pop();
+            Some(benchmark)
+        }
+        _ => None,
+    }
 }
 
-fn inner_main<W: Write>(args: Cli, writer: &mut W) -> Result<(), failure::Error> {
-    let config = index::read_config_from_file(args.config_path)?;
-    let docs = get_path_documents(args.data_path)?;
+fn inner_main<W: Write>(mut args: Cli, writer: &mut W) -> Result<(), failure::Error> {
+    if let Some(command) = args.command {
+        return run_command(command, args.format, writer);
+    }
+    let config_path = args
+        .config_path
+        .ok_or(errors::MinisearchIndexrsError::MissingArgument(
+            "config_path",
+        ))?;
+    let config = index::read_config_from_file(&config_path)?;
+    if args.benchmark == 0 {
+        args.benchmark = trailing_benchmark(&mut args.data_paths).unwrap_or(0);
+    }
+    if args.data_paths.is_empty() {
+        return Err(errors::MinisearchIndexrsError::MissingArgument("data_paths").into());
+    }
+    let docs = input::read_inputs(
+        input::expand_inputs(&args.data_paths)?,
+        &input::read_input_config(&config_path)?,
+    )?;
 
     if args.benchmark > 0 {
-        for (docs, config) in (1..args.benchmark)
-            .into_iter()
-            .map(|_| (docs.clone(), config.clone()))
-        {
-            create_index(docs, config, None)?.into_minisearch_json()?;
+        /* documents are read once so only indexing is measured */
+        let docs = docs.collect::<Result<Vec<_>, _>>()?;
+        for (docs, config) in (1..args.benchmark).map(|_| (docs.clone(), config.clone())) {
+            create_index(
+                input::Documents::new(docs.into_iter().map(Ok)),
+                config,
+                None,
+            )?
+            .into_minisearch_json(args.format)?;
         }
     } else {
-        let progress = ProgressBar::new(docs.len().try_into().unwrap());
+        let progress = progress_bar(&docs);
         writeln!(
             writer,
             "{}",
-            create_index(docs, config, Some(&progress))?.into_minisearch_json()?
+            create_index(docs, config, Some(&progress))?.into_minisearch_json(args.format)?
         )?;
     }
     Ok(())
 }
 
+fn run_command<W: Write>(
+    command: Command,
+    format: serializer::Format,
+    writer: &mut W,
+) -> Result<(), failure::Error> {
+    match command {
+        Command::Add {
+            config_path,
+            index_path,
+            data_paths,
+        } => {
+            let config = index::read_config_from_file(&config_path)?;
+            let mut index = index::read_index_from_file(index_path, config)?;
+            let docs = input::read_inputs(
+                input::expand_inputs(&data_paths)?,
+                &input::read_input_config(&config_path)?,
+            )?;
+            let progress = progress_bar(&docs);
+            add_documents(&mut index, docs, Some(&progress))?;
+            writeln!(writer, "{}", index.into_minisearch_json(format)?)?;
+        }
+        Command::Remove {
+            config_path,
+            index_path,
+            ids_path,
+        } => {
+            let config = index::read_config_from_file(config_path)?;
+            let mut index = index::read_index_from_file(index_path, config)?;
+            let ids = get_path_ids(ids_path)?;
+            index.remove_documents(ids.iter())?;
+            writeln!(writer, "{}", index.into_minisearch_json(format)?)?;
+        }
+        Command::Search {
+            config_path,
+            index_path,
+            query,
+            json,
+        } => {
+            let config = index::read_config_from_file(config_path)?;
+            let index = index::read_index_from_file(index_path, config)?;
+            let query = if json {
+                serde_json::from_str(&query)?
+            } else {
+                search::Query::Terms(query)
+            };
+            let results = index.search_query(&query, index.search_options());
+            writeln!(writer, "{}", serde_json::to_string(&results)?)?;
+        }
+        Command::Suggest {
+            config_path,
+            index_path,
+            queries,
+        } => {
+            let config = index::read_config_from_file(config_path)?;
+            let index = index::read_index_from_file(index_path, config)?;
+            for query in queries.iter() {
+                let suggestions = index.auto_suggest(query, index.auto_suggest_options());
+                writeln!(writer, "{}", serde_json::to_string(&suggestions)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn main() {
     env_logger::init();
     let args = Cli::from_args();
@@ -154,6 +314,19 @@ mod tests {
     use serde_json::json;
     use tempfile::NamedTempFile;
 
+    #[test]
+    fn test_trailing_benchmark() {
+        let data = NamedTempFile::new().unwrap();
+        let data = data.path().to_string_lossy().into_owned();
+        let mut data_paths = vec![data.clone(), "10".to_owned()];
+        assert_eq!(trailing_benchmark(&mut data_paths), Some(10));
+        assert_eq!(data_paths, vec![data.clone()]);
+        assert_eq!(trailing_benchmark(&mut data_paths), None);
+        let mut data_paths = vec![data.clone(), "shard".to_owned()];
+        assert_eq!(trailing_benchmark(&mut data_paths), None);
+        assert_eq!(data_paths.len(), 2);
+    }
+
     #[test]
     fn test_integration() {
         let mut output = Vec::<u8>::new();
@@ -170,9 +343,11 @@ mod tests {
         .unwrap();
         inner_main(
             Cli {
-                config_path: config.path().to_path_buf(),
-                data_path: data.path().to_path_buf(),
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![data.path().to_string_lossy().into_owned()],
                 benchmark: 0,
+                format: serializer::Format::Legacy,
+                command: None,
             },
             &mut output,
         )
@@ -261,4 +436,270 @@ mod tests {
             }),
         );
     }
+
+    #[test]
+    fn test_integration_v2() {
+        let mut output = Vec::<u8>::new();
+        let mut config = NamedTempFile::new().unwrap();
+        config
+            .write_all(r#"{"fields":["a","b"],"store_fields":["a"]}"#.as_bytes())
+            .unwrap();
+
+        let mut data = NamedTempFile::new().unwrap();
+        data.write_all(
+            r#"[{"id":"bar","a":"1","b":"123 1","c":"124"},{"id":"foo","a":"a","b":"b","c":"cd"}]"#
+                .as_bytes(),
+        )
+        .unwrap();
+        inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut output,
+        )
+        .unwrap();
+        let mut json: JSONValue =
+            serde_json::from_str(std::str::from_utf8(&output).unwrap()).unwrap();
+        json["index"]
+            .as_array_mut()
+            .unwrap()
+            .sort_by_key(|entry| entry[0].as_str().unwrap().to_owned());
+        assert_json_eq!(
+            json,
+            json!({
+               "averageFieldLength" : [1.0, 1.5],
+               "documentCount" : 2,
+               "documentIds" : {
+                  "0" : "bar",
+                  "1" : "foo"
+               },
+               "fieldIds" : {
+                  "a" : 0,
+                  "b" : 1
+               },
+               "fieldLength" : {
+                  "0" : [1, 2],
+                  "1" : [1, 1]
+               },
+               "dirtCount": 0,
+               "index" : [
+                  ["1", {"0": {"0": 1}, "1": {"0": 1}}],
+                  ["123", {"1": {"0": 1}}],
+                  ["a", {"0": {"1": 1}}],
+                  ["b", {"1": {"1": 1}}]
+               ],
+               "nextId" : 2,
+               "serializationVersion": 2,
+               "storedFields": {
+                   "0": {
+                       "a": "1"
+                   },
+                   "1": {
+                       "a": "a"
+                   }
+               }
+            }),
+        );
+    }
+
+    #[test]
+    fn test_add_command() {
+        let mut config = NamedTempFile::new().unwrap();
+        config
+            .write_all(r#"{"fields":["a","b"],"store_fields":["a"]}"#.as_bytes())
+            .unwrap();
+        let mut first_data = NamedTempFile::new().unwrap();
+        first_data
+            .write_all(r#"[{"id":"bar","a":"1","b":"123"}]"#.as_bytes())
+            .unwrap();
+        let mut second_data = NamedTempFile::new().unwrap();
+        second_data
+            .write_all(r#"[{"id":"foo","a":"a","b":"b 123"}]"#.as_bytes())
+            .unwrap();
+        let mut all_data = NamedTempFile::new().unwrap();
+        all_data
+            .write_all(
+                r#"[{"id":"bar","a":"1","b":"123"},{"id":"foo","a":"a","b":"b 123"}]"#.as_bytes(),
+            )
+            .unwrap();
+
+        let mut index = NamedTempFile::new().unwrap();
+        inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![first_data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut index,
+        )
+        .unwrap();
+
+        let mut updated = Vec::<u8>::new();
+        inner_main(
+            Cli {
+                config_path: None,
+                data_paths: vec![],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: Some(Command::Add {
+                    config_path: config.path().to_path_buf(),
+                    index_path: index.path().to_path_buf(),
+                    data_paths: vec![second_data.path().to_string_lossy().into_owned()],
+                }),
+            },
+            &mut updated,
+        )
+        .unwrap();
+
+        let mut expected = Vec::<u8>::new();
+        inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![all_data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut expected,
+        )
+        .unwrap();
+
+        let mut updated: JSONValue = serde_json::from_slice(&updated).unwrap();
+        let mut expected: JSONValue = serde_json::from_slice(&expected).unwrap();
+        for json in [&mut updated, &mut expected] {
+            json["index"]
+                .as_array_mut()
+                .unwrap()
+                .sort_by_key(|entry| entry[0].as_str().unwrap().to_owned());
+        }
+        assert_eq!(updated["nextId"], 2);
+        assert_json_eq!(updated, expected);
+    }
+
+    #[test]
+    fn test_remove_command() {
+        let mut config = NamedTempFile::new().unwrap();
+        config
+            .write_all(r#"{"fields":["a"],"store_fields":["a"]}"#.as_bytes())
+            .unwrap();
+        let mut data = NamedTempFile::new().unwrap();
+        data.write_all(
+            r#"[{"id":"bar","a":"x y"},{"id":"foo","a":"y"},{"id":3,"a":"z"}]"#.as_bytes(),
+        )
+        .unwrap();
+        let mut ids = NamedTempFile::new().unwrap();
+        ids.write_all(r#"["bar",3]"#.as_bytes()).unwrap();
+
+        let mut index = NamedTempFile::new().unwrap();
+        inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut index,
+        )
+        .unwrap();
+
+        let mut output = Vec::<u8>::new();
+        inner_main(
+            Cli {
+                config_path: None,
+                data_paths: vec![],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: Some(Command::Remove {
+                    config_path: config.path().to_path_buf(),
+                    index_path: index.path().to_path_buf(),
+                    ids_path: ids.path().to_path_buf(),
+                }),
+            },
+            &mut output,
+        )
+        .unwrap();
+        let json: JSONValue = serde_json::from_slice(&output).unwrap();
+        assert_json_eq!(
+            json,
+            json!({
+               "averageFieldLength" : [1.0],
+               "documentCount" : 1,
+               "documentIds" : {"1" : "foo"},
+               "fieldIds" : {"a" : 0},
+               "fieldLength" : {"1" : [1]},
+               "dirtCount": 0,
+               "index" : [["y", {"0": {"1": 1}}]],
+               "nextId" : 3,
+               "serializationVersion": 2,
+               "storedFields": {"1": {"a": "y"}}
+            }),
+        );
+    }
+
+    #[test]
+    fn test_invalid_id() {
+        let mut config = NamedTempFile::new().unwrap();
+        config
+            .write_all(r#"{"fields":["a"],"storeFields":[],"idField":"slug"}"#.as_bytes())
+            .unwrap();
+        let mut data = NamedTempFile::new().unwrap();
+        data.write_all(r#"[{"slug":"bar","a":"x"},{"slug":null,"a":"y"}]"#.as_bytes())
+            .unwrap();
+        let err = inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut Vec::<u8>::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}: document at index 1: invalid id null: ids cannot be null, arrays or objects",
+                data.path().display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_duplicate_id() {
+        let mut config = NamedTempFile::new().unwrap();
+        config
+            .write_all(r#"{"fields":["a"],"storeFields":[]}"#.as_bytes())
+            .unwrap();
+        let mut data = NamedTempFile::new().unwrap();
+        data.write_all(
+            r#"[{"id":"bar","a":"x"},{"id":"foo","a":"y"},{"id":"bar","a":"z"}]"#.as_bytes(),
+        )
+        .unwrap();
+        let err = inner_main(
+            Cli {
+                config_path: Some(config.path().to_path_buf()),
+                data_paths: vec![data.path().to_string_lossy().into_owned()],
+                benchmark: 0,
+                format: serializer::Format::V2,
+                command: None,
+            },
+            &mut Vec::<u8>::new(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                r#"{0}: document at index 2: duplicate id "bar", first seen at index 0 of {0}"#,
+                data.path().display()
+            )
+        );
+    }
 }
@@ -0,0 +1,1011 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use patricia_tree::{node::Node, PatriciaMap};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map as JSONMap, Value as JSONValue};
+
+use crate::index::Index;
+use crate::serializer;
+use crate::term_processor::TermContext;
+
+/// Parameters of the BM25 scoring, defaulting to the ones used by MiniSearch.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Bm25Params {
+    /// Term frequency saturation.
+    pub k: f64,
+    /// Length normalization impact.
+    pub b: f64,
+    /// Frequency normalization lower bound.
+    pub d: f64,
+}
+
+impl Default for Bm25Params {
+    fn default() -> Self {
+        Bm25Params {
+            k: 1.2,
+            b: 0.7,
+            d: 0.5,
+        }
+    }
+}
+
+/// Relative weights of the expanded matches, compared to exact matches.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct SearchWeights {
+    pub fuzzy: f64,
+    pub prefix: f64,
+}
+
+impl Default for SearchWeights {
+    fn default() -> Self {
+        SearchWeights {
+            fuzzy: 0.45,
+            prefix: 0.375,
+        }
+    }
+}
+
+/// How the results of several terms or queries are combined.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CombineWith {
+    /// Documents matching any of them.
+    #[default]
+    #[serde(rename = "OR", alias = "or")]
+    Or,
+    /// Documents matching all of them.
+    #[serde(rename = "AND", alias = "and")]
+    And,
+    /// Documents matching the first one but none of the others.
+    #[serde(rename = "AND_NOT", alias = "and_not")]
+    AndNot,
+}
+
+/// Mirrors MiniSearch's `searchOptions`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Fields to search in, all of them if not set.
+    pub fields: Option<Vec<String>>,
+    /// Score multiplier of the matches in each field, 1 if not set.
+    pub boost: HashMap<String, f64>,
+    #[serde(alias = "combineWith")]
+    pub combine_with: CombineWith,
+    /// Also match indexed terms starting with each query term.
+    pub prefix: bool,
+    /// Maximum edit distance of the indexed terms matching each query term.
+    /// Below 1 it is a fraction of the query term length, capped by
+    /// `max_fuzzy`. `true` in the configuration stands for 0.2.
+    #[serde(deserialize_with = "deserialize_fuzzy")]
+    pub fuzzy: f64,
+    #[serde(alias = "maxFuzzy")]
+    pub max_fuzzy: usize,
+    pub weights: SearchWeights,
+    pub bm25: Bm25Params,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            fields: None,
+            boost: HashMap::new(),
+            combine_with: CombineWith::Or,
+            prefix: false,
+            fuzzy: 0.0,
+            max_fuzzy: 6,
+            weights: SearchWeights::default(),
+            bm25: Bm25Params::default(),
+        }
+    }
+}
+
+impl SearchOptions {
+    /// The defaults of `autoSuggestOptions`, where all the terms must match
+    /// like in MiniSearch.
+    pub fn auto_suggest() -> Self {
+        SearchOptions {
+            combine_with: CombineWith::And,
+            ..SearchOptions::default()
+        }
+    }
+}
+
+/// A tree of queries, like MiniSearch's query objects. Each node can
+/// override the options inherited from its parent.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Query {
+    Terms(String),
+    Tree {
+        queries: Vec<Query>,
+        #[serde(flatten)]
+        options: QueryOptions,
+    },
+}
+
+/// Search options overridden by a `Query` node.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct QueryOptions {
+    pub fields: Option<Vec<String>>,
+    pub boost: Option<HashMap<String, f64>>,
+    // aliases are ignored in flattened structs
+    #[serde(rename = "combineWith", alias = "combine_with")]
+    pub combine_with: Option<CombineWith>,
+    pub prefix: Option<bool>,
+    #[serde(deserialize_with = "deserialize_optional_fuzzy")]
+    pub fuzzy: Option<f64>,
+}
+
+impl QueryOptions {
+    fn apply(&self, options: &SearchOptions) -> SearchOptions {
+        SearchOptions {
+            fields: self.fields.clone().or_else(|| options.fields.clone()),
+            boost: self.boost.clone().unwrap_or_else(|| options.boost.clone()),
+            combine_with: self.combine_with.unwrap_or(options.combine_with),
+            prefix: self.prefix.unwrap_or(options.prefix),
+            fuzzy: self.fuzzy.unwrap_or(options.fuzzy),
+            ..options.clone()
+        }
+    }
+}
+
+fn deserialize_optional_fuzzy<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_fuzzy(deserializer).map(Some)
+}
+
+/// Deserializes `autoSuggestOptions`, which combine the terms with AND
+/// unless `combineWith` is set.
+pub fn deserialize_auto_suggest_options<'de, D>(deserializer: D) -> Result<SearchOptions, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut options = JSONMap::deserialize(deserializer)?;
+    if !options.contains_key("combineWith") && !options.contains_key("combine_with") {
+        options.insert("combineWith".to_owned(), "AND".into());
+    }
+    SearchOptions::deserialize(JSONValue::Object(options)).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_fuzzy<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Fuzzy {
+        Enabled(bool),
+        Distance(f64),
+    }
+    Ok(match Fuzzy::deserialize(deserializer)? {
+        Fuzzy::Enabled(true) => 0.2,
+        Fuzzy::Enabled(false) => 0.0,
+        Fuzzy::Distance(distance) => distance,
+    })
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub id: JSONValue,
+    pub score: f64,
+    /// Indexed terms that matched.
+    pub terms: Vec<String>,
+    /// Query terms that produced a match.
+    #[serde(rename = "queryTerms")]
+    pub query_terms: Vec<String>,
+    /// Fields where each of `terms` was found.
+    #[serde(rename = "match")]
+    pub matches: BTreeMap<String, Vec<String>>,
+    #[serde(flatten)]
+    pub stored_fields: JSONMap<String, JSONValue>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub suggestion: String,
+    pub terms: Vec<String>,
+    pub score: f64,
+}
+
+#[derive(Default)]
+struct RawResult {
+    score: f64,
+    query_terms: Vec<String>,
+    /* [(term, [field])], in the order the terms matched */
+    matches: Vec<(String, Vec<String>)>,
+}
+
+impl RawResult {
+    fn fields_mut(&mut self, term: &str) -> &mut Vec<String> {
+        let position = match self.matches.iter().position(|(t, _)| t == term) {
+            Some(position) => position,
+            None => {
+                self.matches.push((term.to_owned(), vec![]));
+                self.matches.len() - 1
+            }
+        };
+        &mut self.matches[position].1
+    }
+
+    fn merge(&mut self, other: RawResult) {
+        self.score += other.score;
+        for term in other.query_terms.iter() {
+            push_unique(&mut self.query_terms, term);
+        }
+        for (term, fields) in other.matches.into_iter() {
+            *self.fields_mut(&term) = fields;
+        }
+    }
+}
+
+/* {documentId: result} */
+type RawResults = HashMap<usize, RawResult>;
+
+/* [(term, [field])] of a query token */
+type TokenTerms = Vec<(String, Vec<String>)>;
+
+fn calc_bm25_score(
+    term_freq: usize,
+    matching_count: usize,
+    total_count: usize,
+    field_length: usize,
+    avg_field_length: f64,
+    params: &Bm25Params,
+) -> f64 {
+    let inv_doc_freq = (1.0
+        + (total_count as f64 - matching_count as f64 + 0.5) / (matching_count as f64 + 0.5))
+        .ln();
+    let term_freq = term_freq as f64;
+    inv_doc_freq
+        * (params.d
+            + term_freq * (params.k + 1.0)
+                / (term_freq
+                    + params.k
+                        * (1.0 - params.b + params.b * field_length as f64 / avg_field_length)))
+}
+
+/// Finds the keys of `map` within `max_distance` (Levenshtein, counted in
+/// chars) of `term`, pruning the subtrees that can no longer match.
+pub fn fuzzy_get<'a, V>(
+    map: &'a PatriciaMap<V>,
+    term: &str,
+    max_distance: usize,
+) -> Vec<(String, &'a V, usize)> {
+    let query = term.chars().collect::<Vec<_>>();
+    let mut matches = vec![];
+    let first_row = (0..=query.len()).collect::<Vec<_>>();
+    let root: &Node<V> = map.as_ref();
+    fuzzy_walk(
+        root,
+        &mut vec![],
+        0,
+        &first_row,
+        &query,
+        max_distance,
+        &mut matches,
+    );
+    matches
+}
+
+/* `previous` is the last Levenshtein row of the parent, which is updated in
+ * a row of its own for each char of the label */
+fn fuzzy_walk<'a, V>(
+    node: &'a Node<V>,
+    key: &mut Vec<u8>,
+    mut decoded: usize,
+    previous: &[usize],
+    query: &[char],
+    max_distance: usize,
+    matches: &mut Vec<(String, &'a V, usize)>,
+) {
+    let parent_len = key.len();
+    key.extend_from_slice(node.label());
+    // labels may split a char, which is then completed by the children
+    let valid = match std::str::from_utf8(&key[decoded..]) {
+        Ok(valid) => valid,
+        Err(e) => std::str::from_utf8(&key[decoded..decoded + e.valid_up_to()]).unwrap(),
+    };
+    let mut row = previous.to_vec();
+    for c in valid.chars() {
+        let mut diagonal = row[0];
+        row[0] += 1;
+        for (j, q) in query.iter().enumerate() {
+            let above = row[j + 1];
+            let substitution = diagonal + if *q == c { 0 } else { 1 };
+            row[j + 1] = substitution.min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+        if row.iter().min().unwrap() > &max_distance {
+            key.truncate(parent_len);
+            return;
+        }
+    }
+    decoded += valid.len();
+
+    if decoded == key.len() {
+        if let Some(value) = node.value() {
+            let distance = row[query.len()];
+            if distance <= max_distance {
+                matches.push((String::from_utf8(key.clone()).unwrap(), value, distance));
+            }
+        }
+    }
+    let mut child = node.child();
+    while let Some(node) = child {
+        fuzzy_walk(node, key, decoded, &row, query, max_distance, matches);
+        child = node.sibling();
+    }
+    key.truncate(parent_len);
+}
+
+fn push_unique(values: &mut Vec<String>, value: &str) {
+    if !values.iter().any(|v| v == value) {
+        values.push(value.to_owned());
+    }
+}
+
+fn combine_or(mut a: RawResults, b: RawResults) -> RawResults {
+    for (small_id, result) in b.into_iter() {
+        match a.get_mut(&small_id) {
+            None => {
+                a.insert(small_id, result);
+            }
+            Some(existing) => existing.merge(result),
+        }
+    }
+    a
+}
+
+fn combine_and(mut a: RawResults, b: RawResults) -> RawResults {
+    b.into_iter()
+        .filter_map(|(small_id, result)| {
+            let mut existing = a.remove(&small_id)?;
+            existing.merge(result);
+            Some((small_id, existing))
+        })
+        .collect()
+}
+
+fn combine_and_not(mut a: RawResults, b: RawResults) -> RawResults {
+    for small_id in b.keys() {
+        a.remove(small_id);
+    }
+    a
+}
+
+fn combine<I>(results: I, combine_with: CombineWith) -> RawResults
+where
+    I: Iterator<Item = RawResults>,
+{
+    let combinator = match combine_with {
+        CombineWith::Or => combine_or,
+        CombineWith::And => combine_and,
+        CombineWith::AndNot => combine_and_not,
+    };
+    results.reduce(combinator).unwrap_or_default()
+}
+
+impl Index {
+    /// Runs a query with MiniSearch's semantics: the query is tokenized and
+    /// processed like the documents, and the results of its terms are
+    /// combined with `options.combine_with`, best scores first.
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Vec<SearchResult> {
+        self.search_query(&Query::Terms(query.to_owned()), options)
+    }
+
+    pub fn search_query(&self, query: &Query, options: &SearchOptions) -> Vec<SearchResult> {
+        let results = self.execute_query(query, options);
+        self.to_search_results(results)
+    }
+
+    /* the terms of each query token, with the fields to look them up in: a
+     * token is processed for each searched field, as they can be stemmed
+     * differently. Tokens without terms, like stop words, are left out. */
+    fn query_terms(&self, query: &str, options: &SearchOptions) -> Vec<TokenTerms> {
+        let fields = match &options.fields {
+            Some(fields) => fields.clone(),
+            None => {
+                let mut fields = self.field_ids.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(_, id)| **id);
+                fields.into_iter().map(|(field, _)| field.clone()).collect()
+            }
+        };
+        self.tokenizer
+            .tokenize(query)
+            .map(|token| {
+                let mut terms: TokenTerms = vec![];
+                for field in fields.iter() {
+                    let context = TermContext {
+                        field: Some(field),
+                        ..TermContext::default()
+                    };
+                    for term in self.term_processor.process(token, context) {
+                        match terms.iter_mut().find(|(t, _)| *t == term) {
+                            Some((_, term_fields)) => term_fields.push(field.clone()),
+                            None => terms.push((term, vec![field.clone()])),
+                        }
+                    }
+                }
+                terms
+            })
+            .filter(|terms| !terms.is_empty())
+            .collect()
+    }
+
+    /* the results of a token's terms, each looked up in its fields */
+    fn execute_token(&self, terms: &TokenTerms, options: &SearchOptions) -> RawResults {
+        combine(
+            terms
+                .iter()
+                .map(|(term, fields)| self.execute_term(term, fields, options)),
+            CombineWith::Or,
+        )
+    }
+
+    fn execute_query(&self, query: &Query, options: &SearchOptions) -> RawResults {
+        match query {
+            Query::Terms(query) => combine(
+                self.query_terms(query, options)
+                    .iter()
+                    .map(|terms| self.execute_token(terms, options)),
+                options.combine_with,
+            ),
+            Query::Tree {
+                queries,
+                options: query_options,
+            } => {
+                let options = query_options.apply(options);
+                combine(
+                    queries
+                        .iter()
+                        .map(|query| self.execute_query(query, &options)),
+                    options.combine_with,
+                )
+            }
+        }
+    }
+
+    /// Suggests completions for a partial query, like MiniSearch's
+    /// `autoSuggest`: the results of the terms are combined with
+    /// `options.combine_with` and the last one is also matched as a prefix.
+    /// Results matching the same terms are grouped into one suggestion,
+    /// scored with their average.
+    pub fn auto_suggest(&self, query: &str, options: &SearchOptions) -> Vec<Suggestion> {
+        let tokens = self.query_terms(query, options);
+        let results = combine(
+            tokens.iter().enumerate().map(|(i, terms)| {
+                if i + 1 == tokens.len() && !options.prefix {
+                    let options = SearchOptions {
+                        prefix: true,
+                        ..options.clone()
+                    };
+                    self.execute_token(terms, &options)
+                } else {
+                    self.execute_token(terms, options)
+                }
+            }),
+            options.combine_with,
+        );
+
+        let mut suggestions: Vec<(Suggestion, usize)> = vec![];
+        for result in self.to_search_results(results).into_iter() {
+            let phrase = result.terms.join(" ");
+            match suggestions.iter_mut().find(|(s, _)| s.suggestion == phrase) {
+                Some((suggestion, count)) => {
+                    suggestion.score += result.score;
+                    *count += 1;
+                }
+                None => suggestions.push((
+                    Suggestion {
+                        suggestion: phrase,
+                        terms: result.terms,
+                        score: result.score,
+                    },
+                    1,
+                )),
+            }
+        }
+        let mut suggestions = suggestions
+            .into_iter()
+            .map(|(suggestion, count)| Suggestion {
+                score: suggestion.score / count as f64,
+                ..suggestion
+            })
+            .collect::<Vec<_>>();
+        suggestions.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        suggestions
+    }
+
+    fn execute_term(&self, term: &str, fields: &[String], options: &SearchOptions) -> RawResults {
+        let mut results = RawResults::new();
+        if let Some(postings) = self.map.get(term) {
+            self.term_results(term, term, 1.0, postings, fields, options, &mut results);
+        }
+        let term_length = term.chars().count();
+        let mut fuzzy_matches = if options.fuzzy > 0.0 {
+            let max_distance = if options.fuzzy < 1.0 {
+                options
+                    .max_fuzzy
+                    .min((term_length as f64 * options.fuzzy).round() as usize)
+            } else {
+                options.fuzzy as usize
+            };
+            fuzzy_get(&self.map, term, max_distance)
+        } else {
+            vec![]
+        };
+        if options.prefix {
+            for (prefixed, postings) in self.map.iter_prefix(term.as_bytes()) {
+                let prefixed = match String::from_utf8(prefixed) {
+                    Ok(prefixed) => prefixed,
+                    Err(_) => continue,
+                };
+                let length = prefixed.chars().count();
+                let distance = length - term_length;
+                if distance == 0 {
+                    continue;
+                }
+                fuzzy_matches.retain(|(fuzzy, _, _)| fuzzy != &prefixed);
+                // the weight decays slowly as the matched term gets longer
+                let weight = options.weights.prefix * length as f64
+                    / (length as f64 + 0.3 * distance as f64);
+                self.term_results(
+                    term,
+                    &prefixed,
+                    weight,
+                    postings,
+                    fields,
+                    options,
+                    &mut results,
+                );
+            }
+        }
+        for (fuzzy, postings, distance) in fuzzy_matches.into_iter() {
+            if distance == 0 {
+                continue;
+            }
+            // unlike prefix matches, the weight decays fast with the distance
+            let length = fuzzy.chars().count() as f64;
+            let weight = options.weights.fuzzy * length / (length + distance as f64);
+            self.term_results(
+                term,
+                &fuzzy,
+                weight,
+                postings,
+                fields,
+                options,
+                &mut results,
+            );
+        }
+        results
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn term_results(
+        &self,
+        source_term: &str,
+        derived_term: &str,
+        weight: f64,
+        postings: &[(usize, usize)],
+        fields: &[String],
+        options: &SearchOptions,
+        results: &mut RawResults,
+    ) {
+        /* the postings of replaced documents may not be purged yet */
+        let postings = if self.removed.is_empty() {
+            Cow::Borrowed(postings)
+        } else {
+            Cow::Owned(
+                postings
+                    .iter()
+                    .filter(|(small_id, _)| !self.removed.contains(small_id))
+                    .copied()
+                    .collect(),
+            )
+        };
+        for (field_id, frequencies) in serializer::term_frequencies(&postings).into_iter() {
+            let field = match self.field_ids.iter().find(|(_, id)| **id == field_id) {
+                Some((field, _)) => field,
+                None => continue,
+            };
+            if !fields.contains(field) {
+                continue;
+            }
+            let boost = options.boost.get(field).unwrap_or(&1.0);
+            let avg_field_length = *self.field_num_tokens.get(&field_id).unwrap_or(&0) as f64
+                / self.document_count as f64;
+            let matching_count = frequencies.len();
+            for (small_id, term_freq) in frequencies.into_iter() {
+                let field_length = self
+                    .field_length
+                    .get(&small_id)
+                    .and_then(|lengths| lengths.get(&field_id))
+                    .unwrap_or(&0);
+                let score = weight
+                    * boost
+                    * calc_bm25_score(
+                        term_freq,
+                        matching_count,
+                        self.document_count,
+                        *field_length,
+                        avg_field_length,
+                        &options.bm25,
+                    );
+                let result = results.entry(small_id).or_default();
+                result.score += score;
+                push_unique(&mut result.query_terms, source_term);
+                push_unique(result.fields_mut(derived_term), field);
+            }
+        }
+    }
+
+    fn to_search_results(&self, results: RawResults) -> Vec<SearchResult> {
+        let mut results = results
+            .into_iter()
+            .filter_map(|(small_id, result)| {
+                let id = self.document_ids.get(&small_id.to_string())?.clone();
+                let stored_fields = match self.stored_fields.get(&small_id.to_string()) {
+                    Some(JSONValue::Object(stored_fields)) => stored_fields.clone(),
+                    _ => JSONMap::new(),
+                };
+                // documents matching more query terms rank higher
+                let quality = result.query_terms.len().max(1) as f64;
+                Some((
+                    small_id,
+                    SearchResult {
+                        id,
+                        score: result.score * quality,
+                        terms: result
+                            .matches
+                            .iter()
+                            .map(|(term, _)| term.clone())
+                            .collect(),
+                        query_terms: result.query_terms,
+                        matches: result.matches.into_iter().collect(),
+                        stored_fields,
+                    },
+                ))
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|(a_id, a), (b_id, b)| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a_id.cmp(b_id))
+        });
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexConfig;
+    use serde_json::json;
+
+    fn billboard_index() -> Index {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title", "artist"],
+            "storeFields": ["year"],
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        for doc in [
+            json!({"id": 1, "title": "wooly bully", "artist": "sam the sham and the pharaohs", "year": "1965"}),
+            json!({"id": 2, "title": "help", "artist": "the beatles", "year": "1965"}),
+            json!({"id": 3, "title": "hey jude", "artist": "the beatles", "year": "1968"}),
+            json!({"id": 4, "title": "i want to hold your hand", "artist": "the beatles", "year": "1964"}),
+        ] {
+            index
+                .add_document(serde_json::from_value(doc).unwrap())
+                .unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn test_calc_bm25_score() {
+        let score = calc_bm25_score(1, 1, 4, 2, 3.0, &Bm25Params::default());
+        let expected =
+            (1.0f64 + 3.5 / 1.5).ln() * (0.5 + 2.2 / (1.0 + 1.2 * (0.3 + 0.7 * 2.0 / 3.0)));
+        assert!((score - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_search() {
+        let index = billboard_index();
+        let results = index.search("Beatles Jude", &SearchOptions::default());
+        assert_eq!(
+            results.iter().map(|r| r.id.clone()).collect::<Vec<_>>(),
+            vec![json!(3), json!(2), json!(4)]
+        );
+        assert_eq!(results[0].terms, vec!["beatles", "jude"]);
+        assert_eq!(results[0].query_terms, vec!["beatles", "jude"]);
+        assert_eq!(results[0].matches["jude"], vec!["title"]);
+        assert_eq!(results[0].stored_fields["year"], json!("1968"));
+        assert!(results[0].score > results[1].score);
+        assert_eq!(results[1].score, results[2].score);
+
+        assert!(index.search("stones", &SearchOptions::default()).is_empty());
+        assert!(index.search("", &SearchOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_prefix_search() {
+        let index = billboard_index();
+        assert!(index.search("beat", &SearchOptions::default()).is_empty());
+
+        let options = SearchOptions {
+            prefix: true,
+            ..Default::default()
+        };
+        let results = index.search("he", &options);
+        assert_eq!(
+            results.iter().map(|r| r.id.clone()).collect::<Vec<_>>(),
+            vec![json!(2), json!(3)]
+        );
+        assert_eq!(results[0].terms, vec!["help"]);
+        assert_eq!(results[0].query_terms, vec!["he"]);
+
+        // exact matches are not weighted down
+        let exact = index.search("hey", &options);
+        let prefix = index.search("he", &options);
+        assert!(exact[0].score > prefix[1].score);
+
+        let heavier = index.search(
+            "he",
+            &SearchOptions {
+                prefix: true,
+                weights: SearchWeights {
+                    prefix: 0.75,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        assert!((heavier[0].score - 2.0 * results[0].score).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fuzzy_get() {
+        let map = [
+            "beatles", "beetles", "bee", "beatle", "battles", "señor", "senior",
+        ]
+        .iter()
+        .map(|term| (term, ()))
+        .collect::<PatriciaMap<_>>();
+        let mut matches = fuzzy_get(&map, "beatles", 1)
+            .into_iter()
+            .map(|(term, _, distance)| (term, distance))
+            .collect::<Vec<_>>();
+        matches.sort();
+        assert_eq!(
+            matches,
+            vec![
+                ("beatle".to_owned(), 1),
+                ("beatles".to_owned(), 0),
+                ("beetles".to_owned(), 1),
+            ]
+        );
+
+        // distances count chars, not bytes
+        let mut matches = fuzzy_get(&map, "senor", 1)
+            .into_iter()
+            .map(|(term, _, distance)| (term, distance))
+            .collect::<Vec<_>>();
+        matches.sort();
+        assert_eq!(
+            matches,
+            vec![("senior".to_owned(), 1), ("señor".to_owned(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_search() {
+        let index = billboard_index();
+        assert!(index
+            .search("beetles", &SearchOptions::default())
+            .is_empty());
+
+        let options: SearchOptions = serde_json::from_value(json!({"fuzzy": true})).unwrap();
+        let results = index.search("beetles jude", &options);
+        assert_eq!(
+            results.iter().map(|r| r.id.clone()).collect::<Vec<_>>(),
+            vec![json!(3), json!(2), json!(4)]
+        );
+        assert_eq!(results[1].terms, vec!["beatles"]);
+        assert_eq!(results[1].query_terms, vec!["beetles"]);
+
+        let exact = index.search("beatles", &options);
+        assert!(exact[0].score > results[1].score);
+
+        let options: SearchOptions = serde_json::from_value(json!({"fuzzy": 2})).unwrap();
+        assert_eq!(index.search("jadu", &options)[0].id, json!(3));
+        let options: SearchOptions =
+            serde_json::from_value(json!({"fuzzy": 0.5, "maxFuzzy": 1})).unwrap();
+        assert!(index.search("jadu", &options).is_empty());
+    }
+
+    #[test]
+    fn test_auto_suggest() {
+        let index = billboard_index();
+        let suggestions = index.auto_suggest("the b", &SearchOptions::auto_suggest());
+        assert_eq!(
+            suggestions
+                .iter()
+                .map(|s| s.suggestion.as_str())
+                .collect::<Vec<_>>(),
+            vec!["the bully", "the beatles"]
+        );
+        assert_eq!(suggestions[1].terms, vec!["the", "beatles"]);
+
+        let suggestions = index.auto_suggest("h", &SearchOptions::auto_suggest());
+        assert_eq!(
+            suggestions
+                .iter()
+                .map(|s| s.suggestion.as_str())
+                .collect::<Vec<_>>(),
+            vec!["hand hold", "help", "hey"]
+        );
+        assert!(suggestions[0].score > suggestions[1].score);
+
+        let options = SearchOptions {
+            fuzzy: 0.2,
+            ..SearchOptions::auto_suggest()
+        };
+        let suggestions = index.auto_suggest("hey juda", &options);
+        assert_eq!(suggestions[0].suggestion, "hey jude");
+        assert!(index
+            .auto_suggest("wooly jude", &SearchOptions::auto_suggest())
+            .is_empty());
+        let mut suggestions = index
+            .auto_suggest("wooly jude", &SearchOptions::default())
+            .into_iter()
+            .map(|s| s.suggestion)
+            .collect::<Vec<_>>();
+        suggestions.sort();
+        assert_eq!(suggestions, vec!["jude", "wooly"]);
+
+        let options = deserialize_auto_suggest_options(json!({"fuzzy": true})).unwrap();
+        assert_eq!(options.combine_with, CombineWith::And);
+        let options = deserialize_auto_suggest_options(json!({"combineWith": "OR"})).unwrap();
+        assert_eq!(options.combine_with, CombineWith::Or);
+    }
+
+    #[test]
+    fn test_combine_with() {
+        let index = billboard_index();
+        let ids =
+            |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let options: SearchOptions = serde_json::from_value(json!({"combineWith": "AND"})).unwrap();
+        assert_eq!(ids(index.search("beatles jude", &options)), vec![json!(3)]);
+        let options: SearchOptions =
+            serde_json::from_value(json!({"combineWith": "and_not"})).unwrap();
+        assert_eq!(
+            ids(index.search("beatles jude", &options)),
+            vec![json!(2), json!(4)]
+        );
+    }
+
+    #[test]
+    fn test_search_query() {
+        let index = billboard_index();
+        let ids =
+            |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+
+        let query: Query = serde_json::from_value(json!({
+            "queries": [
+                "beatles",
+                {"queries": ["jud", "hand"], "prefix": true, "combineWith": "OR"},
+            ],
+            "combineWith": "AND_NOT",
+        }))
+        .unwrap();
+        assert_eq!(
+            ids(index.search_query(&query, &SearchOptions::default())),
+            vec![json!(2)]
+        );
+
+        let query: Query = serde_json::from_value(json!({
+            "queries": [
+                {"queries": ["beetles"], "fuzzy": 1},
+                "wooly bully",
+            ],
+            "combineWith": "OR",
+        }))
+        .unwrap();
+        assert_eq!(
+            ids(index.search_query(&query, &SearchOptions::default())),
+            vec![json!(1), json!(2), json!(3), json!(4)]
+        );
+
+        let query: Query = serde_json::from_value(json!({
+            "queries": ["hey jude", "help"],
+            "combineWith": "AND",
+        }))
+        .unwrap();
+        let options: SearchOptions = serde_json::from_value(json!({"combineWith": "AND"})).unwrap();
+        assert!(index.search_query(&query, &options).is_empty());
+        let query: Query = serde_json::from_value(json!({
+            "queries": ["hey jude", "beatles"],
+            "combineWith": "AND",
+        }))
+        .unwrap();
+        assert_eq!(ids(index.search_query(&query, &options)), vec![json!(3)]);
+    }
+
+    #[test]
+    fn test_fields_and_boost() {
+        let index = billboard_index();
+        let ids =
+            |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+
+        // "the" is only in artists
+        assert_eq!(index.search("the", &SearchOptions::default()).len(), 4);
+        let options: SearchOptions = serde_json::from_value(json!({"fields": ["title"]})).unwrap();
+        assert!(index.search("the", &options).is_empty());
+        assert_eq!(ids(index.search("hand", &options)), vec![json!(4)]);
+
+        let plain = index.search("hey beatles", &SearchOptions::default());
+        let options: SearchOptions =
+            serde_json::from_value(json!({"boost": {"title": 3}})).unwrap();
+        let boosted = index.search("hey beatles", &options);
+        assert_eq!(boosted[0].id, json!(3));
+        assert_eq!(boosted[0].matches["hey"], vec!["title"]);
+        assert!(boosted[0].score > plain[0].score);
+        assert_eq!(boosted[1].score, plain[1].score);
+
+        let query: Query = serde_json::from_value(json!({
+            "queries": ["the", {"queries": ["wooly"], "fields": ["artist"]}],
+            "combineWith": "AND",
+            "fields": ["title", "artist"],
+        }))
+        .unwrap();
+        assert!(index
+            .search_query(&query, &SearchOptions::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_field_term_processing() {
+        let config: IndexConfig = serde_json::from_value(json!({
+            "fields": ["title", "artist"],
+            "storeFields": [],
+            "processTerm": [
+                {"type": "lowercase"},
+                {"type": "synonyms", "synonyms": {"nyc": "new york city"}},
+                {"type": "stem", "fieldLanguages": {"title": "english"}},
+            ],
+            "searchOptions": {"fields": ["title"]},
+        }))
+        .unwrap();
+        let mut index = Index::new(config);
+        for doc in [
+            json!({"id": 1, "title": "running", "artist": "running man"}),
+            json!({"id": 2, "title": "new york", "artist": "nyc"}),
+        ] {
+            index
+                .add_document(serde_json::from_value(doc).unwrap())
+                .unwrap();
+        }
+        let options = index.search_options();
+        assert_eq!(index.search("running", options).len(), 1);
+        assert_eq!(index.search("run", options).len(), 1);
+        // the artist is not stemmed
+        let results = index.search("running", &SearchOptions::default());
+        assert_eq!(results[0].matches["run"], vec!["title"]);
+        assert_eq!(results[0].matches["running"], vec!["artist"]);
+        // synonyms are only added when indexing, whatever the fields
+        assert!(index.search("nyc", options).is_empty());
+    }
+}
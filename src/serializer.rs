@@ -1,8 +1,33 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
 use patricia_tree::{node::Node, PatriciaMap};
 use serde_json::{json, Map as JSONMap, Value as JSONValue};
 
+use crate::errors::MinisearchIndexrsError;
+
+/// Layout of the serialized index.
+///
+/// `Legacy` is the radix tree understood by MiniSearch 3, `V2` is the
+/// `serializationVersion: 2` format read by current MiniSearch releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Legacy,
+    V2,
+}
+
+impl FromStr for Format {
+    type Err = MinisearchIndexrsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" | "1" => Ok(Format::Legacy),
+            "v2" | "2" => Ok(Format::V2),
+            _ => Err(MinisearchIndexrsError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
 pub fn field_ids_json(field_ids_src: HashMap<String, usize>) -> JSONMap<String, JSONValue> {
     let mut field_ids = JSONMap::new();
     for (k, v) in field_ids_src.into_iter() {
@@ -22,6 +47,23 @@ pub fn average_field_length_json(
     average_field_length
 }
 
+pub fn average_field_length_array_json(
+    field_num_tokens: HashMap<usize, usize>,
+    num_fields: usize,
+    document_count: f64,
+) -> Vec<JSONValue> {
+    (0..num_fields)
+        .map(|field_id| {
+            let num_tokens = field_num_tokens.get(&field_id).unwrap_or(&0);
+            if document_count > 0.0 {
+                (*num_tokens as f64 / document_count).into()
+            } else {
+                0.0.into()
+            }
+        })
+        .collect()
+}
+
 pub fn field_length_json(
     field_length_src: HashMap<usize, HashMap<usize, usize>>,
 ) -> JSONMap<String, JSONValue> {
@@ -36,6 +78,51 @@ pub fn field_length_json(
     field_length
 }
 
+pub fn field_length_array_json(
+    field_length_src: HashMap<usize, HashMap<usize, usize>>,
+    num_fields: usize,
+) -> JSONMap<String, JSONValue> {
+    let mut field_length = JSONMap::new();
+    for (small_id, field_lengths) in field_length_src.into_iter() {
+        let lengths = (0..num_fields)
+            .map(|field_id| (*field_lengths.get(&field_id).unwrap_or(&0)).into())
+            .collect::<Vec<JSONValue>>();
+        field_length.insert(small_id.to_string(), lengths.into());
+    }
+    field_length
+}
+
+/* {fieldId: {documentId: count}} */
+pub(crate) fn term_frequencies(
+    postings: &[(usize, usize)],
+) -> BTreeMap<usize, HashMap<usize, usize>> {
+    let mut tree = BTreeMap::new();
+    for (small_id, field_id) in postings {
+        let subtree = tree
+            .entry(*field_id)
+            .or_insert_with(HashMap::<usize, usize>::new);
+        *subtree.entry(*small_id).or_insert(0) += 1;
+    }
+    tree
+}
+
+pub fn index_json(map: PatriciaMap<Vec<(usize, usize)>>) -> Result<Vec<JSONValue>, failure::Error> {
+    let mut index = vec![];
+    for (term, postings) in map.into_iter() {
+        let term = String::from_utf8(term)?;
+        let mut fields = JSONMap::new();
+        for (field_id, counts) in term_frequencies(&postings).into_iter() {
+            let mut ds = JSONMap::new();
+            for (small_id, count) in counts.into_iter() {
+                ds.insert(small_id.to_string(), count.into());
+            }
+            fields.insert(field_id.to_string(), ds.into());
+        }
+        index.push(json!([term, fields]));
+    }
+    Ok(index)
+}
+
 pub fn map_json(
     map: PatriciaMap<Vec<(usize, usize)>>,
 ) -> Result<JSONMap<String, JSONValue>, failure::Error> {
@@ -57,35 +144,22 @@ pub fn map_json(
         let mut val = JSONMap::new();
         if level + 1 > stack.len() {
             if let Some(nodes) = node.value() {
-                let mut tree = HashMap::new();
-                for (small_id, field_id) in nodes {
-                    let subtree = match tree.entry(field_id) {
-                        Entry::Occupied(o) => o.into_mut(),
-                        Entry::Vacant(v) => v.insert(HashMap::<usize, usize>::new()),
-                    };
-                    match subtree.entry(*small_id) {
-                        Entry::Occupied(o) => *o.into_mut() += 1,
-                        Entry::Vacant(v) => {
-                            v.insert(1);
-                        }
-                    };
-                }
-                for (small_id, counts) in tree.into_iter() {
+                let mut fields = JSONMap::new();
+                for (field_id, counts) in term_frequencies(nodes).into_iter() {
                     let df = counts.len();
                     let mut ds = JSONMap::new();
-                    for (field_id, count) in counts.into_iter() {
-                        ds.insert(field_id.to_string(), count.into());
+                    for (small_id, count) in counts.into_iter() {
+                        ds.insert(small_id.to_string(), count.into());
                     }
-                    val.insert(
-                        "".to_string(),
+                    fields.insert(
+                        field_id.to_string(),
                         json!({
-                            small_id.to_string(): {
-                                "df": df,
-                                "ds": ds,
-                            }
+                            "df": df,
+                            "ds": ds,
                         }),
                     );
                 }
+                val.insert("".to_string(), fields.into());
             }
             stack.push((label, val));
         }
@@ -99,12 +173,128 @@ pub fn map_json(
     Ok(index)
 }
 
+pub(crate) fn invalid_index(reason: &str) -> failure::Error {
+    MinisearchIndexrsError::InvalidIndex(reason.to_owned()).into()
+}
+
+fn parse_id(id: &str) -> Result<usize, failure::Error> {
+    id.parse()
+        .map_err(|_| invalid_index(&format!("{} is not a numeric id", id)))
+}
+
+fn parse_count(count: &JSONValue) -> Result<usize, failure::Error> {
+    count
+        .as_u64()
+        .map(|count| count as usize)
+        .ok_or_else(|| invalid_index(&format!("{} is not a count", count)))
+}
+
+pub fn parse_field_ids_json(
+    field_ids: &JSONMap<String, JSONValue>,
+) -> Result<HashMap<String, usize>, failure::Error> {
+    field_ids
+        .iter()
+        .map(|(field, field_id)| Ok((field.clone(), parse_count(field_id)?)))
+        .collect()
+}
+
+/// Reads both `{documentId: {fieldId: length}}` (legacy) and
+/// `{documentId: [length, ...]}` (v2) field lengths.
+pub fn parse_field_length_json(
+    field_length: &JSONMap<String, JSONValue>,
+) -> Result<HashMap<usize, HashMap<usize, usize>>, failure::Error> {
+    let mut field_length_dst = HashMap::new();
+    for (small_id, lengths) in field_length.iter() {
+        let lengths = match lengths {
+            JSONValue::Array(lengths) => lengths
+                .iter()
+                .enumerate()
+                .filter(|(_, length)| !length.is_null())
+                .map(|(field_id, length)| Ok((field_id, parse_count(length)?)))
+                .collect::<Result<HashMap<_, _>, failure::Error>>()?,
+            JSONValue::Object(lengths) => lengths
+                .iter()
+                .map(|(field_id, length)| Ok((parse_id(field_id)?, parse_count(length)?)))
+                .collect::<Result<HashMap<_, _>, failure::Error>>()?,
+            _ => return Err(invalid_index("field lengths must be an array or an object")),
+        };
+        field_length_dst.insert(parse_id(small_id)?, lengths);
+    }
+    Ok(field_length_dst)
+}
+
+/* {fieldId: {documentId: count}}, with the counts nested in `ds` before v2 */
+fn parse_term_json(data: &JSONValue) -> Result<Vec<(usize, usize)>, failure::Error> {
+    let fields = data
+        .as_object()
+        .ok_or_else(|| invalid_index("term data must be an object"))?;
+    let mut postings = vec![];
+    for (field_id, entry) in fields.iter() {
+        let field_id = parse_id(field_id)?;
+        let counts = entry
+            .get("ds")
+            .unwrap_or(entry)
+            .as_object()
+            .ok_or_else(|| invalid_index("term frequencies must be an object"))?;
+        for (small_id, count) in counts.iter() {
+            let small_id = parse_id(small_id)?;
+            /* `repeat_n` needs rust 1.82 */
+            #[allow(clippy::manual_repeat_n)]
+            postings.extend(std::iter::repeat((small_id, field_id)).take(parse_count(count)?));
+        }
+    }
+    Ok(postings)
+}
+
+/// Inverse of `map_json`.
+pub fn parse_map_json(
+    index: &JSONMap<String, JSONValue>,
+) -> Result<PatriciaMap<Vec<(usize, usize)>>, failure::Error> {
+    let prefix = index.get("_prefix").and_then(|p| p.as_str()).unwrap_or("");
+    let tree = index
+        .get("_tree")
+        .and_then(|t| t.as_object())
+        .ok_or_else(|| invalid_index("missing _tree"))?;
+
+    let mut map = PatriciaMap::new();
+    let mut stack = vec![(prefix.to_owned(), tree)];
+    while let Some((prefix, tree)) = stack.pop() {
+        for (label, val) in tree.iter() {
+            if label.is_empty() {
+                map.insert(&prefix, parse_term_json(val)?);
+            } else {
+                let subtree = val
+                    .as_object()
+                    .ok_or_else(|| invalid_index("tree nodes must be objects"))?;
+                stack.push((format!("{}{}", prefix, label), subtree));
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Inverse of `index_json`. Also accepts the `serializationVersion: 1`
+/// layout.
+pub fn parse_index_json(
+    index: &[JSONValue],
+) -> Result<PatriciaMap<Vec<(usize, usize)>>, failure::Error> {
+    let mut map = PatriciaMap::new();
+    for entry in index.iter() {
+        match entry.as_array().map(Vec::as_slice) {
+            Some([JSONValue::String(term), data]) => {
+                map.insert(term, parse_term_json(data)?);
+            }
+            _ => return Err(invalid_index("index entries must be [term, data] pairs")),
+        }
+    }
+    Ok(map)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_json_diff::assert_json_eq;
     use serde_test::{assert_tokens, Token};
-    use std::array::IntoIter;
 
     #[test]
     fn test_serialize_fields() {
@@ -150,11 +340,14 @@ mod tests {
 
     #[test]
     fn test_field_length_json() {
-        let field_length_src = HashMap::<_, _>::from_iter(IntoIter::new([
-            (1, HashMap::<_, _>::from_iter(IntoIter::new([(1, 4)]))),
+        let field_length_src = HashMap::<_, _>::from_iter(IntoIterator::into_iter([
+            (
+                1,
+                HashMap::<_, _>::from_iter(IntoIterator::into_iter([(1, 4)])),
+            ),
             (
                 3,
-                HashMap::<_, _>::from_iter(IntoIter::new([(1, 5), (2, 6)])),
+                HashMap::<_, _>::from_iter(IntoIterator::into_iter([(1, 5), (2, 6)])),
             ),
         ]));
         let json = field_length_json(field_length_src);
@@ -426,4 +619,80 @@ mod tests {
             ),
         )
     }
+
+    #[test]
+    fn test_map_json_fields() {
+        let mut map = PatriciaMap::new();
+        map.insert("life", vec![(4, 0), (4, 0), (4, 1), (5, 1)]);
+        let json = map_json(map).unwrap();
+        assert_json_eq!(
+            json,
+            json!({
+                "_prefix": "",
+                "_tree": {
+                    "life": {
+                        "": {
+                            "0": {"df": 1, "ds": {"4": 2}},
+                            "1": {"df": 2, "ds": {"4": 1, "5": 1}},
+                        }
+                    }
+                }
+            })
+        )
+    }
+
+    #[test]
+    fn test_index_json() {
+        let mut map = PatriciaMap::new();
+        map.insert("life", vec![(4, 0), (4, 0), (4, 1)]);
+        map.insert("after", vec![(4, 0), (5, 1)]);
+        let json = index_json(map).unwrap();
+        assert_json_eq!(
+            JSONValue::from(json),
+            json!([
+                ["after", {"0": {"4": 1}, "1": {"5": 1}}],
+                ["life", {"0": {"4": 2}, "1": {"4": 1}}],
+            ])
+        )
+    }
+
+    #[test]
+    fn test_parse_map_json() {
+        let mut map = PatriciaMap::new();
+        map.insert("harry", vec![(0, 0), (1, 0)]);
+        map.insert("hat", vec![(1, 1), (1, 1)]);
+        map.insert("h", vec![(2, 0), (2, 1)]);
+        let json = map_json(map).unwrap();
+        let map = parse_map_json(&json).unwrap();
+        let mut terms = map
+            .into_iter()
+            .map(|(term, mut postings)| {
+                postings.sort_unstable();
+                (String::from_utf8(term).unwrap(), postings)
+            })
+            .collect::<Vec<_>>();
+        terms.sort();
+        assert_eq!(
+            terms,
+            vec![
+                ("h".to_owned(), vec![(2, 0), (2, 1)]),
+                ("harry".to_owned(), vec![(0, 0), (1, 0)]),
+                ("hat".to_owned(), vec![(1, 1), (1, 1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_index_json() {
+        let json = json!([
+            ["after", {"0": {"4": 1}, "1": {"5": 1}}],
+            ["life", {"0": {"ds": {"4": 2}}}],
+        ]);
+        let map = parse_index_json(json.as_array().unwrap()).unwrap();
+        let mut after = map.get("after").unwrap().clone();
+        after.sort_unstable();
+        assert_eq!(after, vec![(4, 0), (5, 1)]);
+        assert_eq!(map.get("life"), Some(&vec![(4, 0), (4, 0)]));
+        assert!(parse_index_json(&[json!(["life"])]).is_err());
+    }
 }
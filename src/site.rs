@@ -0,0 +1,298 @@
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{Event, Parser, Tag};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde_json::{Map as JSONMap, Value as JSONValue};
+
+use crate::errors::MinisearchIndexrsError;
+use crate::input::{extension, Document};
+
+pub const PAGE_EXTENSIONS: &[&str] = &["html", "htm", "md", "markdown"];
+
+/* elements whose text is not shown */
+const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "noscript", "template"];
+
+/* elements that separate words, unlike inline ones like `<b>` */
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+lazy_static! {
+    static ref HEADINGS: Selector = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+    static ref TITLE: Selector = Selector::parse("title").unwrap();
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Page {
+    title: Option<String>,
+    body: String,
+    headings: Vec<String>,
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn element_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    visible_text(element, &mut text);
+    collapse_whitespace(&text)
+}
+
+fn visible_text(element: ElementRef, text: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(e) if !HIDDEN_ELEMENTS.contains(&e.name()) => {
+                let block = BLOCK_ELEMENTS.contains(&e.name());
+                if block {
+                    text.push(' ');
+                }
+                if let Some(child) = ElementRef::wrap(child) {
+                    visible_text(child, text);
+                }
+                if block {
+                    text.push(' ');
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn html_page(source: &str) -> Page {
+    let html = Html::parse_document(source);
+    let headings = html
+        .select(&HEADINGS)
+        .map(element_text)
+        .filter(|heading| !heading.is_empty())
+        .collect::<Vec<_>>();
+    let title = html
+        .select(&TITLE)
+        .map(element_text)
+        .find(|title| !title.is_empty())
+        .or_else(|| headings.first().cloned());
+    Page {
+        title,
+        body: element_text(html.root_element()),
+        headings,
+    }
+}
+
+/* the front matter between `---` lines at the start, and the rest */
+fn split_front_matter(source: &str) -> (Option<&str>, &str) {
+    let rest = match source
+        .strip_prefix("---\n")
+        .or_else(|| source.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return (None, source),
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, source)
+}
+
+/* html in markdown is dropped, like tags in html pages */
+fn markdown_page(source: &str) -> Page {
+    let mut texts = vec![];
+    let mut headings = vec![];
+    let mut heading: Option<Vec<String>> = None;
+    for event in Parser::new(source) {
+        match event {
+            Event::Start(Tag::Heading(..)) => heading = Some(vec![]),
+            Event::End(Tag::Heading(..)) => {
+                texts.push(" ".to_owned());
+                if let Some(heading) = heading.take() {
+                    let heading = collapse_whitespace(&heading.join(""));
+                    if !heading.is_empty() {
+                        headings.push(heading);
+                    }
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = heading.as_mut() {
+                    heading.push(text.to_string());
+                }
+                texts.push(text.to_string());
+            }
+            Event::SoftBreak | Event::HardBreak => texts.push(" ".to_owned()),
+            /* inline tags like `**` don't separate words */
+            Event::End(
+                Tag::Paragraph
+                | Tag::Item
+                | Tag::BlockQuote
+                | Tag::CodeBlock(_)
+                | Tag::TableCell
+                | Tag::TableRow
+                | Tag::TableHead
+                | Tag::List(_)
+                | Tag::FootnoteDefinition(_),
+            ) => texts.push(" ".to_owned()),
+            _ => (),
+        }
+    }
+    Page {
+        title: headings.first().cloned(),
+        body: collapse_whitespace(&texts.concat()),
+        headings,
+    }
+}
+
+/// Turns an html or markdown page into a document with its `title` (the
+/// `<title>` or the first heading), the visible text as `body`, and the
+/// `headings`. The fields in the YAML front matter of markdown pages are
+/// added too, replacing those.
+pub fn read_page(path: &Path, id: &str, id_field: &str) -> Result<Document, failure::Error> {
+    let source = std::fs::read_to_string(path)?;
+    let is_markdown = matches!(extension(path).as_deref(), Some("md") | Some("markdown"));
+    let (front_matter, page) = if is_markdown {
+        let (front_matter, content) = split_front_matter(&source);
+        let front_matter = match front_matter {
+            Some(yaml) => match serde_yaml::from_str(yaml)? {
+                JSONValue::Object(fields) => fields,
+                JSONValue::Null => JSONMap::new(),
+                _ => {
+                    return Err(MinisearchIndexrsError::InvalidPage(
+                        "the front matter must be a mapping".to_owned(),
+                    )
+                    .into())
+                }
+            },
+            None => JSONMap::new(),
+        };
+        (front_matter, markdown_page(content))
+    } else {
+        (JSONMap::new(), html_page(&source))
+    };
+
+    let mut document = Document::new();
+    document.insert(id_field.to_owned(), id.into());
+    document.insert(
+        "title".to_owned(),
+        page.title.map_or(JSONValue::Null, Into::into),
+    );
+    document.insert("body".to_owned(), page.body.into());
+    document.insert("headings".to_owned(), page.headings.into());
+    document.extend(front_matter);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_html_page() {
+        let page = html_page(
+            "<html><head><title> Help! </title><style>p {}</style></head>
+             <body><h1>Help</h1><script>var x;</script>
+             <p>Help me if you <b>can</b>,</p><h2>Lyrics</h2><p>I'm feeling down</p></body></html>",
+        );
+        assert_eq!(
+            page,
+            Page {
+                title: Some("Help!".to_owned()),
+                body: "Help Help me if you can, Lyrics I'm feeling down".to_owned(),
+                headings: vec!["Help".to_owned(), "Lyrics".to_owned()],
+            }
+        );
+        assert_eq!(
+            html_page("<h2>Hey <i>Jude</i></h2>").title,
+            Some("Hey Jude".to_owned())
+        );
+        assert_eq!(
+            html_page("<ul><li><b>W</b>ord</li><li>Jude</li></ul>Na<br>na").body,
+            "Word Jude Na na"
+        );
+    }
+
+    #[test]
+    fn test_markdown_page() {
+        let page =
+            markdown_page("# Hey `Jude`\n\nDon't make it <b>bad</b>.\n\n## Take a sad song\n");
+        assert_eq!(
+            page,
+            Page {
+                title: Some("Hey Jude".to_owned()),
+                body: "Hey Jude Don't make it bad. Take a sad song".to_owned(),
+                headings: vec!["Hey Jude".to_owned(), "Take a sad song".to_owned()],
+            }
+        );
+        assert_eq!(
+            markdown_page("**W**ord and [li](x)nk\n\n- Jude\n- Na").body,
+            "Word and link Jude Na"
+        );
+    }
+
+    #[test]
+    fn test_read_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("help.md");
+        std::fs::write(
+            &path,
+            "---\ntitle: Help!\ntags: [rock, pop]\nyear: 1965\n---\n# Help\n\nWon't you please\n",
+        )
+        .unwrap();
+        let document = read_page(&path, "songs/help.md", "id").unwrap();
+        assert_eq!(
+            serde_json::to_value(document).unwrap(),
+            json!({
+                "id": "songs/help.md",
+                "title": "Help!",
+                "body": "Help Won't you please",
+                "headings": ["Help"],
+                "tags": ["rock", "pop"],
+                "year": 1965,
+            })
+        );
+
+        assert_eq!(split_front_matter("---\nno end\n"), (None, "---\nno end\n"));
+        std::fs::write(&path, "---\n- a list\n---\n").unwrap();
+        assert!(read_page(&path, "help.md", "id").is_err());
+
+        let path = dir.path().join("README.MD");
+        std::fs::write(&path, "---\ntitle: Read me\n---\n# Readme\n").unwrap();
+        let document = read_page(&path, "README.MD", "id").unwrap();
+        assert_eq!(document["title"], json!("Read me"));
+    }
+}
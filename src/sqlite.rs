@@ -0,0 +1,155 @@
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use log::debug;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use serde::Deserialize;
+use serde_json::Value as JSONValue;
+
+use crate::errors::MinisearchIndexrsError;
+use crate::input::Document;
+
+pub const SQLITE_EXTENSIONS: &[&str] = &["sqlite", "sqlite3", "db"];
+
+/// Reads documents from SQLite databases with `query`. Each row is a
+/// document whose fields are the columns, and `id_column`, when set, is
+/// copied into the `idField`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct SqliteConfig {
+    query: Option<String>,
+    #[serde(alias = "idColumn")]
+    id_column: Option<String>,
+}
+
+/* integers and reals are numbers, texts are strings and blobs are skipped */
+fn column_value(value: ValueRef) -> Option<JSONValue> {
+    Some(match value {
+        ValueRef::Null => JSONValue::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f).map_or(JSONValue::Null, Into::into),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into(),
+        ValueRef::Blob(_) => return None,
+    })
+}
+
+fn send_rows(
+    connection: &Connection,
+    query: &str,
+    id_column: Option<&str>,
+    id_field: &str,
+    sender: &mpsc::SyncSender<Result<Document, failure::Error>>,
+) -> Result<(), failure::Error> {
+    let mut statement = connection.prepare(query)?;
+    let columns = statement
+        .column_names()
+        .into_iter()
+        .map(|c| c.to_owned())
+        .collect::<Vec<_>>();
+    if let Some(id_column) = id_column {
+        if !columns.iter().any(|c| c == id_column) {
+            return Err(MinisearchIndexrsError::InvalidConfig(format!(
+                "the query has no column {}",
+                id_column
+            ))
+            .into());
+        }
+    }
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let mut document = Document::new();
+        for (i, column) in columns.iter().enumerate() {
+            match column_value(row.get_ref(i)?) {
+                Some(value) => {
+                    if Some(column.as_str()) == id_column {
+                        document.insert(id_field.to_owned(), value.clone());
+                    }
+                    document.insert(column.clone(), value);
+                }
+                None => debug!("skipping blob column {}", column),
+            }
+        }
+        if sender.send(Ok(document)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the query in another thread, sending the rows as they are read.
+pub fn sqlite_documents(
+    path: &Path,
+    config: &SqliteConfig,
+    id_field: &str,
+) -> Result<impl Iterator<Item = Result<Document, failure::Error>>, failure::Error> {
+    let query = config.query.clone().ok_or_else(|| {
+        MinisearchIndexrsError::InvalidConfig(format!(
+            "sqlite.query is needed to read {}",
+            path.display()
+        ))
+    })?;
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let id_column = config.id_column.clone();
+    let id_field = id_field.to_owned();
+    let (sender, receiver) = mpsc::sync_channel(64);
+    thread::spawn(move || {
+        let result = send_rows(
+            &connection,
+            &query,
+            id_column.as_deref(),
+            &id_field,
+            &sender,
+        );
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+        }
+    });
+    Ok(receiver.into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sqlite_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("songs.db");
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE songs (song_id INTEGER, title TEXT, rating REAL, cover BLOB);
+                 INSERT INTO songs VALUES (1, 'Help', 4.5, x'00'), (2, NULL, NULL, NULL);",
+            )
+            .unwrap();
+
+        let config: SqliteConfig = serde_json::from_value(json!({
+            "query": "SELECT * FROM songs ORDER BY song_id",
+            "idColumn": "song_id",
+        }))
+        .unwrap();
+        let documents = sqlite_documents(&path, &config, "id")
+            .unwrap()
+            .map(|d| serde_json::to_value(d.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            documents,
+            vec![
+                json!({"id": 1, "song_id": 1, "title": "Help", "rating": 4.5}),
+                json!({"id": 2, "song_id": 2, "title": null, "rating": null, "cover": null}),
+            ]
+        );
+
+        let config: SqliteConfig =
+            serde_json::from_value(json!({"query": "SELECT title FROM songs", "idColumn": "x"}))
+                .unwrap();
+        let mut documents = sqlite_documents(&path, &config, "id").unwrap();
+        assert_eq!(
+            documents.next().unwrap().unwrap_err().to_string(),
+            "invalid configuration: the query has no column x"
+        );
+        assert!(sqlite_documents(&path, &SqliteConfig::default(), "id").is_err());
+    }
+}
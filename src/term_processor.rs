@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+
+use log::debug;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::Deserialize;
+use serde_json::Value as JSONValue;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::document::extract_field;
+use crate::errors::MinisearchIndexrsError;
+
+/* Lucene's English stop words */
+const ENGLISH_STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// Turns tokens into the terms that are indexed and searched, like
+/// MiniSearch's `processTerm`. It is set with `processTerm` in the
+/// configuration as a list of steps applied in order, e.g.
+/// `[{"type": "nfkc"}, {"type": "lowercase"}, {"type": "stopwords", "list": "english"}]`.
+/// Without it terms are only lowercased.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(transparent)]
+pub struct TermProcessor {
+    steps: Vec<TermStep>,
+}
+
+impl Default for TermProcessor {
+    fn default() -> Self {
+        TermProcessor {
+            steps: vec![TermStep::Lowercase],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "TermStepConfig")]
+pub enum TermStep {
+    Lowercase,
+    /// Unicode NFKC normalization.
+    Nfkc,
+    /// Removes diacritics, turning "Beyoncé" into "Beyonce".
+    FoldDiacritics,
+    /// Drops the terms in the list.
+    Stopwords(HashSet<String>),
+    /// Drops the terms shorter than `min` or longer than `max` chars.
+    Length {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+    /// Adds the synonyms of the term after it, only when indexing.
+    Synonyms(HashMap<String, Vec<String>>),
+    /// Stems with the Snowball stemmer of the document language (read from
+    /// `language_field`), or else of the field, or else `language`.
+    Stem {
+        language: Option<Algorithm>,
+        field_languages: HashMap<String, Algorithm>,
+        language_field: Option<String>,
+    },
+}
+
+/// Where a term comes from. Query terms have no document, and are processed
+/// once for each searched field.
+#[derive(Default, Clone, Copy)]
+pub struct TermContext<'a> {
+    pub field: Option<&'a str>,
+    pub document: Option<&'a HashMap<String, JSONValue>>,
+    /// Whether the term is being indexed rather than searched; synonyms
+    /// are only added then.
+    pub indexing: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum TermStepConfig {
+    Lowercase,
+    Nfkc,
+    FoldDiacritics,
+    /// The stop words are the union of a built-in `list`, the lines of the
+    /// file at `path` and `words`.
+    Stopwords {
+        list: Option<String>,
+        path: Option<String>,
+        #[serde(default)]
+        words: Vec<String>,
+    },
+    Length {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+    /// Synonyms from the file at `path`, with a rule per line, and from
+    /// `synonyms`, like `{"nyc": "new york city"}`.
+    Synonyms {
+        path: Option<String>,
+        #[serde(default)]
+        synonyms: HashMap<String, String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Stem {
+        language: Option<String>,
+        #[serde(default)]
+        field_languages: HashMap<String, String>,
+        language_field: Option<String>,
+    },
+}
+
+/* Snowball stemmers by name or ISO 639-1 code, ignoring the region */
+fn parse_language(language: &str) -> Option<Algorithm> {
+    let language = language.to_lowercase();
+    Some(match language.split(['-', '_']).next().unwrap_or("") {
+        "arabic" | "ar" => Algorithm::Arabic,
+        "danish" | "da" => Algorithm::Danish,
+        "dutch" | "nl" => Algorithm::Dutch,
+        "english" | "en" => Algorithm::English,
+        "finnish" | "fi" => Algorithm::Finnish,
+        "french" | "fr" => Algorithm::French,
+        "german" | "de" => Algorithm::German,
+        "greek" | "el" => Algorithm::Greek,
+        "hungarian" | "hu" => Algorithm::Hungarian,
+        "italian" | "it" => Algorithm::Italian,
+        "norwegian" | "no" | "nb" | "nn" => Algorithm::Norwegian,
+        "portuguese" | "pt" => Algorithm::Portuguese,
+        "romanian" | "ro" => Algorithm::Romanian,
+        "russian" | "ru" => Algorithm::Russian,
+        "spanish" | "es" => Algorithm::Spanish,
+        "swedish" | "sv" => Algorithm::Swedish,
+        "tamil" | "ta" => Algorithm::Tamil,
+        "turkish" | "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+/* Solr-like rules: `nyc, ny => new york city` adds the words on the right to
+ * the terms on the left, and `tv, television` makes the terms synonyms of each
+ * other. Empty lines and lines starting with `#` are ignored. */
+fn add_synonym_rules(synonyms: &mut HashMap<String, Vec<String>>, rules: &str) {
+    for rule in rules.lines().map(|rule| rule.trim()) {
+        if rule.is_empty() || rule.starts_with('#') {
+            continue;
+        }
+        let (terms, words) = match rule.split_once("=>") {
+            Some((terms, words)) => (terms, words.split_whitespace().collect::<Vec<_>>()),
+            None => (rule, rule.split(',').map(|term| term.trim()).collect()),
+        };
+        for term in terms.split(',').map(|term| term.trim()) {
+            add_synonyms(synonyms, term, words.iter().cloned());
+        }
+    }
+}
+
+fn add_synonyms<'a, I>(synonyms: &mut HashMap<String, Vec<String>>, term: &str, words: I)
+where
+    I: Iterator<Item = &'a str>,
+{
+    let term_synonyms = synonyms.entry(term.to_owned()).or_default();
+    for word in words {
+        if word != term && !word.is_empty() && !term_synonyms.iter().any(|w| w == word) {
+            term_synonyms.push(word.to_owned());
+        }
+    }
+}
+
+fn language_from_config(language: String) -> Result<Algorithm, MinisearchIndexrsError> {
+    parse_language(&language).ok_or(MinisearchIndexrsError::UnknownLanguage(language))
+}
+
+impl TryFrom<TermStepConfig> for TermStep {
+    type Error = failure::Error;
+
+    fn try_from(config: TermStepConfig) -> Result<Self, Self::Error> {
+        Ok(match config {
+            TermStepConfig::Lowercase => TermStep::Lowercase,
+            TermStepConfig::Nfkc => TermStep::Nfkc,
+            TermStepConfig::FoldDiacritics => TermStep::FoldDiacritics,
+            TermStepConfig::Stopwords { list, path, words } => {
+                let mut stopwords = words.into_iter().collect::<HashSet<_>>();
+                match list.as_deref() {
+                    Some("english") => {
+                        stopwords.extend(ENGLISH_STOPWORDS.iter().map(|w| w.to_string()))
+                    }
+                    Some(list) => {
+                        return Err(MinisearchIndexrsError::UnknownStopwords(list.to_owned()).into())
+                    }
+                    None => (),
+                }
+                if let Some(path) = path {
+                    stopwords.extend(
+                        fs::read_to_string(path)?
+                            .lines()
+                            .map(|w| w.trim())
+                            .filter(|w| !w.is_empty())
+                            .map(|w| w.to_owned()),
+                    );
+                }
+                TermStep::Stopwords(stopwords)
+            }
+            TermStepConfig::Length { min, max } => TermStep::Length { min, max },
+            TermStepConfig::Synonyms { path, synonyms } => {
+                let mut rules = HashMap::new();
+                if let Some(path) = path {
+                    add_synonym_rules(&mut rules, &fs::read_to_string(path)?);
+                }
+                for (term, words) in synonyms.iter() {
+                    add_synonyms(&mut rules, term, words.split_whitespace());
+                }
+                TermStep::Synonyms(rules)
+            }
+            TermStepConfig::Stem {
+                language,
+                field_languages,
+                language_field,
+            } => TermStep::Stem {
+                language: language.map(language_from_config).transpose()?,
+                field_languages: field_languages
+                    .into_iter()
+                    .map(|(field, language)| Ok((field, language_from_config(language)?)))
+                    .collect::<Result<_, MinisearchIndexrsError>>()?,
+                language_field,
+            },
+        })
+    }
+}
+
+impl TermStep {
+    fn apply(&self, term: String, context: TermContext) -> Vec<String> {
+        match self {
+            TermStep::Synonyms(synonyms) if context.indexing => {
+                let words = synonyms.get(&term).cloned().unwrap_or_default();
+                std::iter::once(term).chain(words).collect()
+            }
+            _ => self.map(term, context).into_iter().collect(),
+        }
+    }
+
+    fn map(&self, term: String, context: TermContext) -> Option<String> {
+        match self {
+            TermStep::Lowercase => Some(term.to_lowercase()),
+            TermStep::Nfkc => Some(term.nfkc().collect()),
+            /* recomposed, or hangul syllables stay split into jamo */
+            TermStep::FoldDiacritics => Some(
+                term.nfd()
+                    .filter(|c| !is_combining_mark(*c))
+                    .nfc()
+                    .collect(),
+            ),
+            TermStep::Stopwords(stopwords) => {
+                if stopwords.contains(&term) {
+                    None
+                } else {
+                    Some(term)
+                }
+            }
+            TermStep::Length { min, max } => {
+                let length = term.chars().count();
+                if min.is_some_and(|min| length < min) || max.is_some_and(|max| length > max) {
+                    None
+                } else {
+                    Some(term)
+                }
+            }
+            TermStep::Synonyms(_) => Some(term),
+            TermStep::Stem {
+                language,
+                field_languages,
+                language_field,
+            } => {
+                let document_language = language_field
+                    .as_ref()
+                    .zip(context.document)
+                    .and_then(|(language_field, document)| extract_field(document, language_field))
+                    .and_then(|language| {
+                        let language = language.as_str()?;
+                        let algorithm = parse_language(language);
+                        if algorithm.is_none() {
+                            debug!("unknown document language {}", language);
+                        }
+                        algorithm
+                    });
+                let algorithm = document_language
+                    .or_else(|| context.field.and_then(|f| field_languages.get(f).cloned()))
+                    .or(*language);
+                match algorithm {
+                    Some(algorithm) => Some(Stemmer::create(algorithm).stem(&term).into_owned()),
+                    None => Some(term),
+                }
+            }
+        }
+    }
+}
+
+impl TermProcessor {
+    /// Returns the terms for a token, without repeats nor empty terms. It is
+    /// empty if the token has to be dropped.
+    pub fn process(&self, token: &str, context: TermContext) -> Vec<String> {
+        let mut terms = vec![token.to_owned()];
+        for step in self.steps.iter() {
+            terms = terms
+                .into_iter()
+                .flat_map(|term| step.apply(term, context))
+                .collect();
+        }
+        let mut unique_terms: Vec<String> = vec![];
+        for term in terms {
+            if !term.is_empty() && !unique_terms.contains(&term) {
+                unique_terms.push(term);
+            }
+        }
+        unique_terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    const NONE: Vec<String> = vec![];
+
+    fn processor(steps: serde_json::Value) -> TermProcessor {
+        serde_json::from_value(steps).unwrap()
+    }
+
+    #[test]
+    fn test_default() {
+        let processor = TermProcessor::default();
+        assert_eq!(
+            processor.process("Beyoncé", TermContext::default()),
+            vec!["beyoncé"]
+        );
+        assert_eq!(processor.process("", TermContext::default()), NONE);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let processor = processor(json!([{"type": "nfkc"}, {"type": "foldDiacritics"}]));
+        assert_eq!(
+            processor.process("Beyoncé", TermContext::default()),
+            vec!["Beyonce"]
+        );
+        assert_eq!(
+            processor.process("ﬁve", TermContext::default()),
+            vec!["five"]
+        );
+        assert_eq!(
+            processor.process("Ｒｏｃｋ", TermContext::default()),
+            vec!["Rock"]
+        );
+        let folded = processor.process("한국", TermContext::default());
+        assert_eq!(folded, vec!["한국"]);
+        assert_eq!(folded[0].chars().count(), 2);
+    }
+
+    #[test]
+    fn test_stopwords() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("live\n\n remix \n".as_bytes()).unwrap();
+        let processor = processor(json!([
+            {"type": "lowercase"},
+            {"type": "stopwords", "list": "english", "path": file.path(), "words": ["feat"]},
+        ]));
+        assert_eq!(processor.process("The", TermContext::default()), NONE);
+        assert_eq!(processor.process("LIVE", TermContext::default()), NONE);
+        assert_eq!(processor.process("remix", TermContext::default()), NONE);
+        assert_eq!(processor.process("feat", TermContext::default()), NONE);
+        assert_eq!(
+            processor.process("Beatles", TermContext::default()),
+            vec!["beatles"]
+        );
+
+        assert!(serde_json::from_value::<TermProcessor>(
+            json!([{"type": "stopwords", "list": "klingon"}])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_length() {
+        let processor = processor(json!([{"type": "length", "min": 2, "max": 4}]));
+        assert_eq!(processor.process("a", TermContext::default()), NONE);
+        assert_eq!(processor.process("ab", TermContext::default()), vec!["ab"]);
+        assert_eq!(processor.process("señor", TermContext::default()), NONE);
+        assert_eq!(
+            processor.process("seño", TermContext::default()),
+            vec!["seño"]
+        );
+    }
+
+    #[test]
+    fn test_stem() {
+        let processor = processor(json!([
+            {"type": "lowercase"},
+            {
+                "type": "stem",
+                "language": "english",
+                "fieldLanguages": {"titulo": "spanish"},
+                "languageField": "lang",
+            },
+        ]));
+        let stem = |token, field, document| {
+            processor
+                .process(
+                    token,
+                    TermContext {
+                        field,
+                        document,
+                        indexing: true,
+                    },
+                )
+                .join(" ")
+        };
+        assert_eq!(stem("Running", None, None), "run");
+        assert_eq!(stem("canciones", None, None), "cancion");
+        assert_eq!(stem("canciones", Some("titulo"), None), "cancion");
+        assert_eq!(stem("corriendo", Some("titulo"), None), "corr");
+
+        let spanish = HashMap::from([("lang".to_owned(), json!("es-AR"))]);
+        let unknown = HashMap::from([("lang".to_owned(), json!("xx"))]);
+        assert_eq!(stem("corriendo", Some("title"), Some(&spanish)), "corr");
+        assert_eq!(stem("running", Some("titulo"), Some(&unknown)), "running");
+        assert_eq!(stem("running", Some("title"), Some(&unknown)), "run");
+
+        assert!(serde_json::from_value::<TermProcessor>(
+            json!([{"type": "stem", "language": "klingon"}])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_synonyms() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("# abbreviations\nnyc, ny => new york city\n\ntv, television\n".as_bytes())
+            .unwrap();
+        let processor = processor(json!([
+            {"type": "lowercase"},
+            {"type": "synonyms", "path": file.path(), "synonyms": {"la": "los angeles"}},
+            {"type": "stopwords", "words": ["los"]},
+        ]));
+        let context = TermContext {
+            field: Some("title"),
+            document: None,
+            indexing: true,
+        };
+        assert_eq!(
+            processor.process("NYC", context),
+            vec!["nyc", "new", "york", "city"]
+        );
+        assert_eq!(
+            processor.process("ny", context),
+            vec!["ny", "new", "york", "city"]
+        );
+        assert_eq!(processor.process("york", context), vec!["york"]);
+        assert_eq!(processor.process("TV", context), vec!["tv", "television"]);
+        assert_eq!(
+            processor.process("television", context),
+            vec!["television", "tv"]
+        );
+        assert_eq!(processor.process("LA", context), vec!["la", "angeles"]);
+        assert_eq!(
+            processor.process("nyc", TermContext::default()),
+            vec!["nyc"]
+        );
+    }
+}